@@ -1,8 +1,6 @@
 #![no_std]
 #![no_main]
 
-use core::{cell::{Cell, RefCell}, error, fmt::Error};
-use cortex_m::interrupt::Mutex;
 // pick a panicking behavior
 use panic_rtt_target as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
 // use panic_abort as _; // requires nightly
@@ -11,17 +9,16 @@ use panic_rtt_target as _; // you can put a breakpoint on `rust_begin_unwind` to
 
 use cortex_m_rt::entry;
 
-use log::{debug, error, info, Level, LevelFilter, Metadata, Record};
+use log::{error, info, Level, LevelFilter, Metadata, Record};
 use rtt_target::{rprintln, rtt_init_print};
-use sfmd_rs::{flash, serial_interface::SerialInterface, FlashInfo, FlashOperations};
+use embedded_hal_bus::spi::ExclusiveDevice;
+use sfmd_rs::{serial_interface::EmbeddedHalSPI, FlashInfo, FlashOperations};
 use stm32f4xx_hal::{
-    gpio::{Output, Pin, PushPull, Speed},
-    hal::spi,
+    gpio::Speed,
     pac,
     prelude::*,
     rcc::RccExt,
     spi::*,
-    timer::SysDelay,
 };
 
 use sfmd_rs::flash::Flash;
@@ -53,51 +50,6 @@ impl log::Log for Logger {
     fn flush(&self) {}
 }
 
-struct SpiDev<'a, SPI, const G: char, const P: u8>
-where
-    SPI: Instance,
-{
-    spi: Spi<SPI>,
-    cs: Pin<G, P, Output<PushPull>>,
-    delay:&'a mut SysDelay,
-}
-
-impl<'a, SPI, const G: char, const P: u8> SpiDev<'a, SPI, G, P>
-where
-    SPI: Instance,
-{
-    pub fn new(spi: Spi<SPI>, cs: Pin<G, P, Output<PushPull>>, delay:&'a mut SysDelay) -> Self {
-        SpiDev { spi, cs, delay }
-    }
-}
-
-impl<'a, SPI, const G: char, const P: u8> SerialInterface for SpiDev<'a, SPI, G, P>
-where
-    SPI: Instance,
-{
-    fn write(&mut self, cmd: &[u8], data:Option<&[u8]>) -> Result<(), core::fmt::Error> {
-        self.cs.set_low();
-        self.spi.write(cmd).unwrap();
-        if let Some(data) = data {
-            self.spi.write(data).unwrap();
-        }
-        self.cs.set_high();
-        Ok(())
-    }
-
-    fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Error> {
-        self.cs.set_low();
-        self.spi.write(cmd).unwrap();
-        self.spi.read(rev).unwrap();
-        self.cs.set_high();
-        Ok(())
-    }
-
-    fn delay(&mut self, ms: u32) {
-        self.delay.delay(ms.millis()); // Assuming 168 MHz clock speed
-    }
-}
-
 #[entry]
 fn main() -> ! {
     log_init();
@@ -130,7 +82,10 @@ fn main() -> ! {
     let mut spi_delay = cp.SYST.delay(&clocks);
 
 
-    let spi_device = SpiDev::new(spi1, cs, &mut spi_delay);
+    let spi_device = EmbeddedHalSPI::new(
+        ExclusiveDevice::new_no_delay(spi1, cs).unwrap(),
+        &mut spi_delay,
+    );
 
     let flash_info = FlashInfo::new(0xEF, 0x40, 0x17, 16 * 1024 * 1024, 4096);
     
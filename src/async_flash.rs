@@ -0,0 +1,412 @@
+use log::{error, info};
+
+use crate::erase::ErasePlan;
+use crate::serial_interface::AsyncSerialInterface;
+use crate::{AddressMode, AsyncFlashOperations, Error, FlashInfo, QuadEnable, define};
+
+/// Async flash struct
+/// I - AsyncSerialInterface
+///
+/// Same behaviour as `Flash`, but busy polling yields to the executor.
+pub struct AsyncFlash<I>
+where
+    I: AsyncSerialInterface,
+{
+    flash_info: FlashInfo,
+    interface: I,
+    /// The chip takes 4-byte addresses on every command
+    enable_address_4_byte: bool,
+    /// 16M segment selected by the extended address or bank register
+    address_segment: u8,
+}
+
+impl<I> AsyncFlash<I>
+where
+    I: AsyncSerialInterface,
+{
+    pub async fn new(interface: I, flash_info: FlashInfo) -> Result<Self, Error<I::Error>> {
+        let enable_address_4_byte = matches!(
+            flash_info.address_mode,
+            AddressMode::Enter4Byte { .. } | AddressMode::Always4Byte
+        );
+        let mut flash = AsyncFlash {
+            flash_info,
+            interface,
+            enable_address_4_byte,
+            address_segment: 0,
+        };
+
+        let mut jedec_id = [0_u8; 3];
+        flash.read_jedec_id(&mut jedec_id).await?;
+
+        let expected = [
+            flash.flash_info.manufacturer_id,
+            flash.flash_info.type_id,
+            flash.flash_info.capacity_id,
+        ];
+        if expected != jedec_id {
+            error!(
+                "JEDEC ID mismatch: expected {:02X} {:02X} {:02X}, got {:02X} {:02X} {:02X}",
+                expected[0], expected[1], expected[2], jedec_id[0], jedec_id[1], jedec_id[2]
+            );
+            return Err(Error::JedecIdMismatch {
+                expected,
+                found: jedec_id,
+            });
+        }
+
+        flash.write_state(true, 0x00).await?;
+        flash.set_4byte_address_mode().await?;
+
+        Ok(flash)
+    }
+
+    async fn read_jedec_id(&mut self, buff: &mut [u8]) -> Result<(), Error<I::Error>> {
+        let cmd = [define::IdCmd::JedecId as u8];
+        self.interface
+            .write_and_read(&cmd, buff)
+            .await
+            .map_err(|e| {
+                error!("Failed to read JEDEC ID");
+                Error::Interface(e)
+            })?;
+        info!("JEDEC ID: {:02X} {:02X} {:02X}", buff[0], buff[1], buff[2]);
+        Ok(())
+    }
+
+    async fn write_enable(&mut self, enable: bool) -> Result<(), Error<I::Error>> {
+        let cmd = if enable {
+            [define::WriteCmd::WriteEnable as u8]
+        } else {
+            [define::WriteCmd::WriteDisable as u8]
+        };
+
+        self.interface.write(&cmd, None).await.map_err(|e| {
+            error!("Failed to write enable");
+            Error::Interface(e)
+        })?;
+        let status = self
+            .wait_busy(self.flash_info.timeouts.write_status)
+            .await?;
+        if enable && (status & define::STATUS::WEL as u8) == 0 {
+            error!("Write enable failed status: {:02X}", status);
+            Err(Error::WriteEnable)
+        } else if !enable && (status & define::STATUS::WEL as u8) != 0 {
+            error!("Write disable failed status: {:02X}", status);
+            Err(Error::WriteEnable)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Poll the status register until BUSY clears or `timeout` microseconds have passed
+    async fn wait_busy(&mut self, timeout: u32) -> Result<u8, Error<I::Error>> {
+        let interval = (timeout / 100).clamp(10, 10_000);
+        let mut elapsed = 0_u32;
+        loop {
+            let status = self.read_status().await?;
+            if (status & define::STATUS::BUSY as u8) == 0 {
+                return Ok(status);
+            }
+            if elapsed >= timeout {
+                error!("Flash is busy for more than {} us", timeout);
+                return Err(Error::BusyTimeout);
+            }
+            self.interface.delay_us(interval).await;
+            elapsed = elapsed.saturating_add(interval);
+        }
+    }
+
+    async fn set_4byte_address_mode(&mut self) -> Result<(), Error<I::Error>> {
+        let (cmd, write_enable) = match self.flash_info.address_mode {
+            AddressMode::Enter4Byte { write_enable } => {
+                ([define::ModeCmd::Enter4Byte as u8], write_enable)
+            }
+            AddressMode::Always4Byte => return Ok(()),
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                // The register content is unknown after a reset or a previous run
+                self.address_segment = u8::MAX;
+                return self.select_segment(0).await;
+            }
+            // A previous run may have left the chip in 4-byte mode
+            AddressMode::ThreeByte | AddressMode::FourByteOpcodes => {
+                ([define::ModeCmd::Exit4Byte as u8], true)
+            }
+        };
+        if write_enable {
+            self.write_enable(true).await?;
+        }
+        let ret = self.interface.write(&cmd, None).await.map_err(|e| {
+            error!("Failed to set 4-byte address mode");
+            Error::Interface(e)
+        });
+        if write_enable {
+            let _ = self.write_enable(false).await;
+        }
+        ret
+    }
+
+    /// Fill `cmd` with the opcode and address of a read, program or erase, returning its length
+    async fn address_command(
+        &mut self,
+        opcode: u8,
+        address: u32,
+        cmd: &mut [u8; 5],
+    ) -> Result<usize, Error<I::Error>> {
+        let (opcode, len) = match self.flash_info.address_mode {
+            AddressMode::FourByteOpcodes => match define::four_byte_opcode(opcode) {
+                Some(opcode) => (opcode, 4),
+                None => {
+                    error!("No 4-byte opcode for {:02X}", opcode);
+                    return Err(Error::Unsupported);
+                }
+            },
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                self.select_segment((address >> 24) as u8).await?;
+                (opcode, 3)
+            }
+            _ => (opcode, self.address_len()),
+        };
+        cmd[0] = opcode;
+        for (i, byte) in cmd[1..=len].iter_mut().enumerate() {
+            *byte = (address >> ((len - (i + 1)) * 8)) as u8;
+        }
+        Ok(len + 1)
+    }
+
+    /// Bytes from `address` that one command can read, reads must not cross a segment
+    fn segment_len(&self, address: u32, len: usize) -> usize {
+        match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                len.min((1 << 24) - (address as usize & 0xFF_FFFF))
+            }
+            _ => len,
+        }
+    }
+
+    async fn select_segment(&mut self, segment: u8) -> Result<(), Error<I::Error>> {
+        if self.address_segment == segment {
+            return Ok(());
+        }
+        let opcode = match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress => define::ModeCmd::WriteExtendedAddress as u8,
+            _ => define::ModeCmd::WriteBank as u8,
+        };
+        let timeout = self.flash_info.timeouts.write_status;
+        self.write_command(&[opcode, segment], None, timeout)
+            .await
+            .inspect_err(|_| {
+                error!("Failed to select address segment {}", segment);
+            })?;
+        self.address_segment = segment;
+        Ok(())
+    }
+
+    /// Go back to the first segment, where a 3-byte boot ROM expects it
+    async fn restore_segment(&mut self) -> Result<(), Error<I::Error>> {
+        match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                self.select_segment(0).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn flash_info(&self) -> &FlashInfo {
+        &self.flash_info
+    }
+
+    const fn address_len(&self) -> usize {
+        if self.enable_address_4_byte { 4 } else { 3 }
+    }
+
+    /// Send a command that needs the write enable latch and wait for it to finish
+    async fn write_command(
+        &mut self,
+        cmd: &[u8],
+        data: Option<&[u8]>,
+        timeout: u32,
+    ) -> Result<(), Error<I::Error>> {
+        self.write_enable(true).await?;
+        let ret = match self.interface.write(cmd, data).await {
+            Ok(()) => self.wait_busy(timeout).await.map(|_| ()),
+            Err(e) => Err(Error::Interface(e)),
+        };
+        let _ = self.write_enable(false).await;
+        ret
+    }
+
+    async fn page_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        if data.len() > self.flash_info.page_size {
+            error!("Data size exceeds {} bytes", self.flash_info.page_size);
+            return Err(Error::OutOfBounds);
+        }
+
+        let mut cmd = [0_u8; 5];
+        let cmd_len = self
+            .address_command(define::WriteCmd::PageProgram as u8, address, &mut cmd)
+            .await?;
+        let timeout = self.flash_info.timeouts.page_program;
+        self.write_command(&cmd[..cmd_len], Some(data), timeout)
+            .await
+    }
+}
+
+impl<I> AsyncFlashOperations for AsyncFlash<I>
+where
+    I: AsyncSerialInterface,
+{
+    type Error = Error<I::Error>;
+
+    async fn erase_chip(&mut self) -> Result<(), Self::Error> {
+        let cmd = [define::EraseCmd::Chip as u8];
+        let timeout = self.flash_info.timeouts.chip_erase;
+        self.write_command(&cmd, None, timeout)
+            .await
+            .inspect_err(|_| {
+                error!("Failed to erase chip");
+            })
+    }
+
+    async fn erase(&mut self, address: u32, size: usize) -> Result<(), Self::Error> {
+        self.flash_info.check_erase_alignment(address, size)?;
+        if !self.flash_info.contains(address, size) {
+            return Err(Error::OutOfBounds);
+        }
+        if address == 0 && size == self.flash_info.capacity {
+            return self.erase_chip().await;
+        }
+
+        let erase_types = self.flash_info.erase_types;
+
+        for (addr, erase_type) in ErasePlan::new(&erase_types, address, size) {
+            let mut cmd = [0_u8; 5];
+            let cmd_len = self
+                .address_command(erase_type.opcode, addr, &mut cmd)
+                .await?;
+            let timeout = self.flash_info.erase_timeout(erase_type.size);
+            self.write_command(&cmd[..cmd_len], None, timeout)
+                .await
+                .inspect_err(|_| {
+                    error!("Failed to erase block at address {:08X}", addr);
+                })?;
+        }
+        self.restore_segment().await
+    }
+
+    async fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        if !self.flash_info.contains(address, data.len()) {
+            error!(
+                "Write out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                data.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+
+        // The first chunk only runs to the end of the current page
+        let page_size = self.flash_info.page_size;
+        let mut offset = 0_usize;
+        while offset < data.len() {
+            let addr = address + offset as u32;
+            let len = (page_size - addr as usize % page_size).min(data.len() - offset);
+            self.page_write(addr, &data[offset..offset + len])
+                .await
+                .inspect_err(|_| {
+                    error!("Failed to write data to address {:08X}", addr);
+                })?;
+            offset += len;
+        }
+        self.restore_segment().await
+    }
+
+    async fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        if !self.flash_info.contains(address, buffer.len()) {
+            error!(
+                "Read out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                buffer.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+
+        self.wait_busy(self.flash_info.timeouts.write_status)
+            .await?;
+
+        let mut offset = 0_usize;
+        while offset < buffer.len() {
+            let addr = address + offset as u32;
+            let len = self.segment_len(addr, buffer.len() - offset);
+            let mut cmd = [0_u8; 5];
+            let cmd_len = self
+                .address_command(define::ReadCmd::Data as u8, addr, &mut cmd)
+                .await?;
+            self.interface
+                .write_and_read(&cmd[..cmd_len], &mut buffer[offset..offset + len])
+                .await
+                .map_err(|e| {
+                    error!("Failed to read data from address {:08X}", addr);
+                    Error::Interface(e)
+                })?;
+            offset += len;
+        }
+        self.restore_segment().await
+    }
+
+    async fn read_status(&mut self) -> Result<u8, Self::Error> {
+        let mut buff = [0_u8; 1];
+        let cmd = [define::ReadCmd::Status1 as u8];
+
+        self.interface
+            .write_and_read(&cmd, &mut buff)
+            .await
+            .map_err(|e| {
+                error!("Failed to read status register");
+                Error::Interface(e)
+            })?;
+        Ok(buff[0])
+    }
+
+    async fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Self::Error> {
+        // A 1-byte 0x01 clears SR2, and with it QE, on these parts
+        let mut cmd = [define::WriteCmd::WrietStatus as u8, state, 0];
+        let cmd = if self.flash_info.quad_enable == QuadEnable::Sr2Bit1 {
+            let mut sr2 = [0_u8; 1];
+            self.interface
+                .write_and_read(&[define::ReadCmd::Status2 as u8], &mut sr2)
+                .await
+                .map_err(|e| {
+                    error!("Failed to read status register 2");
+                    Error::Interface(e)
+                })?;
+            cmd[2] = sr2[0];
+            &cmd[..]
+        } else {
+            &cmd[..2]
+        };
+        let timeout = self.flash_info.timeouts.write_status;
+        if is_volatile
+            && self.flash_info.volatile_write_enable == define::WriteCmd::VolatileWriteEnable as u8
+        {
+            // 0x50 does not set WEL, so there is nothing to check or clear
+            let enable = [define::WriteCmd::VolatileWriteEnable as u8];
+            self.interface
+                .write(&enable, None)
+                .await
+                .map_err(Error::Interface)?;
+            self.interface.write(cmd, None).await.map_err(|e| {
+                error!("Failed to write status register");
+                Error::Interface(e)
+            })?;
+            self.wait_busy(timeout).await?;
+            return Ok(());
+        }
+        self.write_command(cmd, None, timeout)
+            .await
+            .inspect_err(|_| {
+                error!("Failed to write status register");
+            })
+    }
+}
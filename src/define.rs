@@ -1,14 +1,19 @@
-
 pub(crate) enum WriteCmd {
     WriteEnable = 0x06,
     WriteDisable = 0x04,
     WrietStatus = 0x01,
+    WriteStatus2 = 0x31,
+    WriteStatus3 = 0x11,
+    VolatileWriteEnable = 0x50,
     PageProgram = 0x02,
 }
 
 pub(crate) enum ReadCmd {
     Status1 = 0x05,
     Status2 = 0x35,
+    Status3 = 0x15,
+    SecurityStatus = 0x2B,
+    FlagStatus = 0x70,
     Data = 0x03,
     Fast = 0x0B,
     FastDual = 0x3B,
@@ -19,6 +24,13 @@ pub(crate) enum ModeCmd {
     HighPerformance = 0xA3,
     ModeReset = 0xFF,
     ReleasePowerDown = 0xAB,
+    ResetEnable = 0x66,
+    Reset = 0x99,
+    ResetF0 = 0xF0,
+    Enter4Byte = 0xB7,
+    Exit4Byte = 0xE9,
+    WriteExtendedAddress = 0xC5,
+    WriteBank = 0x17,
 }
 
 pub(crate) enum IdCmd {
@@ -37,6 +49,8 @@ pub(crate) enum EraseCmd {
     Resume = 0x7A,
 }
 
+/// Status register bits, named as in the datasheets
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum STATUS {
     BUSY = 0b0000_0000_0001,
     WEL = 0b0000_0000_0010,
@@ -48,11 +62,88 @@ pub(crate) enum STATUS {
     SRP0 = 0b0000_1000_0000,
     SRP1 = 0b0001_0000_0000,
     QE = 0b0010_0000_0000,
+    LB1 = 0b1000_0000_0000,
+    LB2 = 0b0001_0000_0000_0000,
+    LB3 = 0b0010_0000_0000_0000,
+    CMP = 0b0100_0000_0000_0000,
+    SUS = 0b1000_0000_0000_0000,
+    WPS = 0b0100_0000_0000_0000_0000,
+    DRV0 = 0b0010_0000_0000_0000_0000_0000,
+    DRV1 = 0b0100_0000_0000_0000_0000_0000,
+    HOLD = 0b1000_0000_0000_0000_0000_0000,
+}
+
+pub(crate) enum SecurityCmd {
+    Read = 0x48,
+    Program = 0x42,
+    Erase = 0x44,
+}
+
+pub(crate) enum LockCmd {
+    BlockLock = 0x36,
+    BlockUnlock = 0x39,
+    ReadBlockLock = 0x3D,
+    GlobalLock = 0x7E,
+    GlobalUnlock = 0x98,
 }
 
 #[cfg(feature = "qspi")]
-pub(crate) enum QUAD_CMD {
-    PAGE_PROGRAM = 0x32,
-    FAST_DUAL = 0x6B,
-    FAST_DUAL_IO = 0xEB,
-}
\ No newline at end of file
+pub(crate) enum QuadCmd {
+    PageProgram = 0x32,
+    FastQuad = 0x6B,
+    FastQuadIo = 0xEB,
+    ReadStatus2Alt = 0x3F,
+    WriteStatus2Alt = 0x3E,
+}
+
+pub(crate) enum FourByteCmd {
+    Read = 0x13,
+    Fast = 0x0C,
+    FastDual = 0x3C,
+    FastDualIo = 0xBC,
+    FastQuad = 0x6C,
+    FastQuadIo = 0xEC,
+    PageProgram = 0x12,
+    QuadPageProgram = 0x34,
+    Sector4k = 0x21,
+    Block32k = 0x5C,
+    Block64k = 0xDC,
+}
+
+/// Dedicated 4-byte address opcode of a 3-byte read, program or erase opcode
+pub(crate) fn four_byte_opcode(opcode: u8) -> Option<u8> {
+    const DATA: u8 = ReadCmd::Data as u8;
+    const FAST: u8 = ReadCmd::Fast as u8;
+    const FAST_DUAL: u8 = ReadCmd::FastDual as u8;
+    const FAST_DUAL_IO: u8 = ReadCmd::FastDualIo as u8;
+    const PAGE_PROGRAM: u8 = WriteCmd::PageProgram as u8;
+    const SECTOR_4K: u8 = EraseCmd::Sector4k as u8;
+    const BLOCK_32K: u8 = EraseCmd::Block32k as u8;
+    const BLOCK_64K: u8 = EraseCmd::Block64k as u8;
+    // Quad opcodes, spelled out as QuadCmd only exists with the qspi feature
+    const FAST_QUAD: u8 = 0x6B;
+    const FAST_QUAD_IO: u8 = 0xEB;
+    const QUAD_PAGE_PROGRAM: u8 = 0x32;
+
+    let opcode = match opcode {
+        DATA => FourByteCmd::Read,
+        FAST => FourByteCmd::Fast,
+        FAST_DUAL => FourByteCmd::FastDual,
+        FAST_DUAL_IO => FourByteCmd::FastDualIo,
+        FAST_QUAD => FourByteCmd::FastQuad,
+        FAST_QUAD_IO => FourByteCmd::FastQuadIo,
+        PAGE_PROGRAM => FourByteCmd::PageProgram,
+        QUAD_PAGE_PROGRAM => FourByteCmd::QuadPageProgram,
+        SECTOR_4K => FourByteCmd::Sector4k,
+        BLOCK_32K => FourByteCmd::Block32k,
+        BLOCK_64K => FourByteCmd::Block64k,
+        _ => return None,
+    };
+    Some(opcode as u8)
+}
+
+#[cfg(feature = "ospi")]
+pub(crate) enum OctalCmd {
+    WriteCr2 = 0x72,
+    WriteVcr = 0x81,
+}
@@ -0,0 +1,101 @@
+use crate::EraseType;
+
+/// Splits a sector-aligned range into erase commands
+///
+/// At every position the largest erase type that is aligned there and still
+/// fits inside the range is picked, so smaller erases only appear at the edges.
+/// The plan stops early on a range not aligned to the smallest erase type, see
+/// `FlashInfo::check_erase_alignment`.
+pub(crate) struct ErasePlan<'a> {
+    erase_types: &'a [Option<EraseType>],
+    address: u64,
+    end: u64,
+}
+
+impl<'a> ErasePlan<'a> {
+    pub(crate) fn new(erase_types: &'a [Option<EraseType>], address: u32, size: usize) -> Self {
+        ErasePlan {
+            erase_types,
+            address: address as u64,
+            end: address as u64 + size as u64,
+        }
+    }
+}
+
+impl Iterator for ErasePlan<'_> {
+    type Item = (u32, EraseType);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.address >= self.end {
+            return None;
+        }
+        let erase_type = self
+            .erase_types
+            .iter()
+            .flatten()
+            .filter(|t| {
+                let size = t.size as u64;
+                self.address.is_multiple_of(size) && self.end - self.address >= size
+            })
+            .max_by_key(|t| t.size)
+            .copied()?;
+
+        let address = self.address as u32;
+        self.address += erase_type.size as u64;
+        Some((address, erase_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    const ERASE_TYPES: [Option<EraseType>; 4] = [
+        Some(EraseType {
+            size: 4096,
+            opcode: 0x20,
+        }),
+        Some(EraseType {
+            size: 32 * 1024,
+            opcode: 0x52,
+        }),
+        Some(EraseType {
+            size: 64 * 1024,
+            opcode: 0xD8,
+        }),
+        None,
+    ];
+
+    fn plan(address: u32, size: usize) -> impl Iterator<Item = (u32, u8)> {
+        ErasePlan::new(&ERASE_TYPES, address, size).map(|(a, t)| (a, t.opcode))
+    }
+
+    #[test]
+    fn largest_aligned_type_first() {
+        let mut expected: Vec<_> = (1..8).map(|i| (i * 0x1000, 0x20)).collect();
+        expected.extend([
+            (0x8000, 0x52),
+            (0x1_0000, 0xD8),
+            (0x2_0000, 0x20),
+            (0x2_1000, 0x20),
+        ]);
+        assert_eq!(plan(0x1000, 0x2_1000).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn whole_blocks() {
+        assert_eq!(
+            plan(0x1_0000, 0x2_0000).collect::<Vec<_>>(),
+            [(0x1_0000, 0xD8), (0x2_0000, 0xD8)]
+        );
+        assert_eq!(plan(0, 0).count(), 0);
+    }
+
+    #[test]
+    fn unaligned_range_is_not_covered() {
+        assert_eq!(plan(0x800, 0x1000).count(), 0);
+        assert_eq!(plan(0, 0x1800).collect::<Vec<_>>(), [(0, 0x20)]);
+    }
+}
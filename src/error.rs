@@ -0,0 +1,30 @@
+/// Errors returned by the flash driver
+///
+/// `E` is the error type of the underlying `SerialInterface`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The serial interface reported an error
+    Interface(E),
+    /// The JEDEC ID read from the chip does not match `FlashInfo`
+    JedecIdMismatch { expected: [u8; 3], found: [u8; 3] },
+    /// The chip stayed busy for longer than the allowed time
+    BusyTimeout,
+    /// The write enable latch did not reach the requested state
+    WriteEnable,
+    /// The access exceeds the flash capacity
+    OutOfBounds,
+    /// Address or size is not aligned to the required boundary
+    NotAligned,
+    /// SFDP signature or basic parameter table is missing or malformed
+    InvalidSfdp,
+    /// The detected part or the interface does not support the operation
+    Unsupported,
+    /// The Quad Enable bit did not reach the requested state
+    QuadEnable,
+    /// The access touches a write-protected area, or the protection could not be changed
+    WriteProtected,
+    /// The caller-provided buffer is smaller than one sector
+    BufferTooSmall,
+    /// Data read back after a write differs from what was written, at `address`
+    VerifyFailed { address: u32 },
+}
@@ -1,10 +1,46 @@
-use core::fmt::Error;
+use core::ops::Range;
 
 use log::{error, info};
 
+use crate::erase::ErasePlan;
+use crate::id::FlashId;
 use crate::serial_interface::SerialInterface;
-use crate::{FlashInfo, FlashOperations, define};
-const PAGE_SIZE: usize = 256;
+use crate::sfdp::SFDP;
+use crate::status::StatusRegisters;
+use crate::{
+    AddressMode, EraseType, Error, FlashInfo, FlashOperations, QuadEnable, SoftReset, define, parts,
+};
+
+mod address;
+mod lock;
+mod mapped;
+#[cfg(feature = "ospi")]
+mod octal;
+mod power;
+mod protect;
+#[cfg(feature = "qspi")]
+mod quad;
+mod read;
+mod reset;
+mod security;
+mod suspend;
+mod update;
+mod xip;
+#[cfg(feature = "ospi")]
+pub use octal::OctalMode;
+#[cfg(feature = "qspi")]
+pub use quad::QuadMode;
+pub use read::ReadMode;
+pub use security::SecurityRegisters;
+
+/// Steps run by `Flash::with_options` before the flash is used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InitOptions {
+    /// Soft reset the chip first, in case a bootloader or brown-out left it in another mode
+    pub reset: bool,
+    /// Leave the block protection bits as they are instead of clearing them
+    pub keep_protection: bool,
+}
 
 /// Flash struct
 /// I - SerialInterface
@@ -17,78 +53,315 @@ where
 {
     flash_info: FlashInfo,
     interface: I,
+    /// The chip takes 4-byte addresses on every command
     enable_address_4_byte: bool,
+    /// 16M segment selected by the extended address or bank register
+    address_segment: u8,
+    suspend_on_read: bool,
+    /// Erase started by `start_erase`, which may still be running
+    erasing: Option<(u32, usize)>,
+    /// A resume was sent since the last suspend
+    resumed: bool,
+    powered_down: bool,
+    auto_power_down: Option<u32>,
+    idle_ms: u32,
+    /// Range protected by the BP bits, refreshed after every status register write
+    protected: Option<Range<u32>>,
+    /// WPS is set, writes check the individual block locks instead
+    block_locks: bool,
+    /// From `InitOptions`, applied again after a reset
+    keep_protection: bool,
+    read_mode: ReadMode,
+    /// The chip is in continuous read mode, see `enter_xip`
+    xip: bool,
+    /// Set while a memory-mapped controller owns the bus
+    memory_mapped: Option<mapped::ExitMapped<I>>,
+    #[cfg(feature = "qspi")]
+    quad_mode: QuadMode,
+    #[cfg(feature = "ospi")]
+    octal_mode: OctalMode,
 }
 
 impl<I> Flash<I>
 where
     I: SerialInterface,
 {
-    pub fn new(interface: I, flash_info: FlashInfo) -> Result<Self, Error> {
-        let capacity = flash_info.capacity;
+    pub fn new(interface: I, flash_info: FlashInfo) -> Result<Self, Error<I::Error>> {
+        Self::with_options(interface, flash_info, InitOptions::default())
+    }
+
+    pub fn with_options(
+        interface: I,
+        flash_info: FlashInfo,
+        options: InitOptions,
+    ) -> Result<Self, Error<I::Error>> {
+        let enable_address_4_byte = matches!(
+            flash_info.address_mode,
+            AddressMode::Enter4Byte { .. } | AddressMode::Always4Byte
+        );
+        // The chip may have been left in deep power-down, waking an awake chip is harmless
+        let powered_down = flash_info.power_down.is_some();
         let mut flash = Flash {
             flash_info,
-            interface: interface,
-            enable_address_4_byte: if capacity > (1 << 24) { true } else { false },
+            interface,
+            enable_address_4_byte,
+            address_segment: 0,
+            suspend_on_read: false,
+            erasing: None,
+            resumed: false,
+            powered_down,
+            auto_power_down: None,
+            idle_ms: 0,
+            protected: None,
+            block_locks: false,
+            keep_protection: options.keep_protection,
+            read_mode: ReadMode::Normal,
+            xip: false,
+            memory_mapped: None,
+            #[cfg(feature = "qspi")]
+            quad_mode: QuadMode::Single,
+            #[cfg(feature = "ospi")]
+            octal_mode: OctalMode::Spi,
         };
 
-        let mut jedec_id = [0_u8; 3];
-
-        if flash.read_jedec_id(&mut jedec_id).is_err() {
-            return Err(Error);
+        flash.wake_for_access()?;
+        if options.reset {
+            flash.reset_chip()?;
         }
 
-        if flash.flash_info.manufacturer_id != jedec_id[0]
-            || flash.flash_info.type_id != jedec_id[1]
-            || flash.flash_info.capacity_id != jedec_id[2]
-        {
+        let mut jedec_id = [0_u8; 3];
+        flash.read_jedec_id(&mut jedec_id)?;
+
+        let expected = [
+            flash.flash_info.manufacturer_id,
+            flash.flash_info.type_id,
+            flash.flash_info.capacity_id,
+        ];
+        if expected != jedec_id {
             error!(
                 "JEDEC ID mismatch: expected {:02X} {:02X} {:02X}, got {:02X} {:02X} {:02X}",
-                flash.flash_info.manufacturer_id,
-                flash.flash_info.type_id,
-                flash.flash_info.capacity_id,
-                jedec_id[0],
-                jedec_id[1],
-                jedec_id[2]
+                expected[0], expected[1], expected[2], jedec_id[0], jedec_id[1], jedec_id[2]
             );
-            return Err(Error);
+            if let Some(part) = parts::find(jedec_id) {
+                error!("Fitted part is {}, Flash::probe picks it up", part.name);
+            }
+            return Err(Error::JedecIdMismatch {
+                expected,
+                found: jedec_id,
+            });
         }
 
-        // reset()
+        flash.apply_setup()?;
+
+        Ok(flash)
+    }
+
+    /// Build the flash from its SFDP tables instead of a hand-written `FlashInfo`
+    pub fn from_sfdp(interface: I) -> Result<Self, Error<I::Error>> {
+        Self::from_sfdp_with_options(interface, InitOptions::default())
+    }
+
+    pub fn from_sfdp_with_options(
+        mut interface: I,
+        options: InitOptions,
+    ) -> Result<Self, Error<I::Error>> {
+        let jedec_id = Self::prepare_probe(&mut interface, options)?;
+        Self::with_sfdp(interface, jedec_id, options)
+    }
+
+    /// Pick the `FlashInfo` from the built-in part table, reading SFDP for unknown parts
+    pub fn probe(interface: I) -> Result<Self, Error<I::Error>> {
+        Self::probe_with_options(interface, InitOptions::default())
+    }
 
-        if flash.write_state(true, 0x00).is_err() {
-            return Err(Error);
+    pub fn probe_with_options(
+        mut interface: I,
+        options: InitOptions,
+    ) -> Result<Self, Error<I::Error>> {
+        let jedec_id = Self::prepare_probe(&mut interface, options)?;
+        match parts::find(jedec_id) {
+            Some(part) => {
+                info!("Found {}", part.name);
+                Self::with_options(interface, part.flash_info(), options)
+            }
+            None => {
+                info!("Unknown part, reading SFDP");
+                Self::with_sfdp(interface, jedec_id, options)
+            }
         }
+    }
 
-        if flash.set_4byte_address_mode().is_err() {
-            return Err(Error);
+    /// Reset if asked and read the JEDEC ID, before the parameters are known
+    fn prepare_probe(interface: &mut I, options: InitOptions) -> Result<[u8; 3], Error<I::Error>> {
+        // SFDP is only readable in SPI mode, so reset first
+        if options.reset {
+            reset::exit_continuous_read(interface)
+                .and_then(|_| reset::soft_reset(interface, SoftReset::EnableReset))
+                .map_err(|e| {
+                    error!("Failed to reset");
+                    Error::Interface(e)
+                })?;
         }
 
-        Ok(flash)
+        let mut jedec_id = [0_u8; 3];
+        let cmd = [define::IdCmd::JedecId as u8];
+        interface.write_and_read(&cmd, &mut jedec_id).map_err(|e| {
+            error!("Failed to read JEDEC ID");
+            Error::Interface(e)
+        })?;
+        Ok(jedec_id)
     }
 
-    // TODO build from SFDP
-    // pub fn build(SFDP)->{
-
-    // }
+    fn with_sfdp(
+        mut interface: I,
+        jedec_id: [u8; 3],
+        options: InitOptions,
+    ) -> Result<Self, Error<I::Error>> {
+        let sfdp = SFDP::new(&mut interface).create()?;
+        let flash_info = FlashInfo::from_sfdp(jedec_id, &sfdp);
+        Self::with_options(interface, flash_info, options)
+    }
 
     // fn reset(&self){
     //     let cmd = [cmd::MODE_CMD::MODE_RESET as u8];
     // }
 
-    fn read_jedec_id(&mut self, buff: &mut [u8]) -> Result<(), Error> {
+    fn read_jedec_id(&mut self, buff: &mut [u8]) -> Result<(), Error<I::Error>> {
         // Read JEDEC ID
         let cmd = [define::IdCmd::JedecId as u8];
-        if self.interface.write_and_read(&cmd, buff).is_ok() {
-            info!("JEDEC ID: {:02X} {:02X} {:02X}", buff[0], buff[1], buff[2]);
+        self.interface.write_and_read(&cmd, buff).map_err(|e| {
+            error!("Failed to read JEDEC ID");
+            Error::Interface(e)
+        })?;
+        info!("JEDEC ID: {:02X} {:02X} {:02X}", buff[0], buff[1], buff[2]);
+        Ok(())
+    }
+
+    /// Read the JEDEC, legacy and manufacturer/device IDs of the fitted part
+    pub fn identify(&mut self) -> Result<FlashId, Error<I::Error>> {
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != OctalMode::Spi {
+            error!("Identification is only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+        self.wake_for_access()?;
+
+        let id = FlashId::read(&mut self.interface).map_err(|e| {
+            error!("Failed to read flash ID");
+            Error::Interface(e)
+        })?;
+        info!(
+            "Flash {} ({:02X} bank {}), type {:02X}, capacity {:02X}, device ID {:02X}",
+            id.manufacturer_name().unwrap_or("unknown"),
+            id.manufacturer,
+            id.continuation,
+            id.memory_type,
+            id.capacity,
+            id.device_id
+        );
+        Ok(id)
+    }
+
+    fn read_register(&mut self, opcode: u8) -> Result<u8, Error<I::Error>> {
+        let mut buff = [0_u8; 1];
+        self.interface
+            .write_and_read(&[opcode], &mut buff)
+            .map_err(|e| {
+                error!("Failed to read register {:02X}", opcode);
+                Error::Interface(e)
+            })?;
+        Ok(buff[0])
+    }
+
+    /// Write a status register command, to the volatile copy if `is_volatile`
+    fn write_status_register(
+        &mut self,
+        cmd: &[u8],
+        is_volatile: bool,
+    ) -> Result<(), Error<I::Error>> {
+        let timeout = self.flash_info.timeouts.write_status;
+        if is_volatile
+            && self.flash_info.volatile_write_enable == define::WriteCmd::VolatileWriteEnable as u8
+        {
+            // 0x50 does not set WEL, so there is nothing to check or clear
+            self.interface
+                .write(&[define::WriteCmd::VolatileWriteEnable as u8], None)
+                .map_err(Error::Interface)?;
+            self.interface.write(cmd, None).map_err(|e| {
+                error!("Failed to write status register");
+                Error::Interface(e)
+            })?;
+            self.wait_busy(timeout)?;
+            return self.refresh_protection();
+        }
+
+        self.write_operation(|s| {
+            s.interface.write(cmd, None).map_err(|e| {
+                error!("Failed to write status register");
+                Error::Interface(e)
+            })?;
+            s.wait_busy(timeout)?;
             Ok(())
+        })?;
+        self.refresh_protection()
+    }
+
+    pub fn read_status_registers(&mut self) -> Result<StatusRegisters, Error<I::Error>> {
+        self.wake_for_access()?;
+        // Registers the part does not have read as 0
+        let mut sr = [self.read_register(define::ReadCmd::Status1 as u8)?, 0, 0];
+        let opcodes = self.flash_info.status_read_opcodes();
+        for (byte, opcode) in sr[1..].iter_mut().zip(opcodes) {
+            if let Some(opcode) = opcode {
+                *byte = self.read_register(opcode)?;
+            }
+        }
+        Ok(StatusRegisters::from_bytes(sr))
+    }
+
+    /// Write the status registers that differ from the chip
+    ///
+    /// With `is_volatile` the values are lost at power-off, which spares the
+    /// non-volatile bits from wear when they are set on every boot.
+    pub fn write_status_registers(
+        &mut self,
+        registers: &StatusRegisters,
+        is_volatile: bool,
+    ) -> Result<(), Error<I::Error>> {
+        let current = self.read_status_registers()?.to_bytes();
+        let new = registers.to_bytes();
+        // BUSY and WEL are read-only
+        let sr1_changed = (current[0] ^ new[0]) & 0xFC != 0;
+        let [sr2, sr3] = self.flash_info.status_read_opcodes();
+        if (sr2.is_none() && current[1] != new[1]) || (sr3.is_none() && current[2] != new[2]) {
+            error!("Status register 2 or 3 is not available on this flash");
+            return Err(Error::Unsupported);
+        }
+
+        if self.flash_info.quad_enable == QuadEnable::Sr2Bit1 {
+            // SR2 is only writable together with SR1
+            if sr1_changed || current[1] != new[1] {
+                let cmd = [define::WriteCmd::WrietStatus as u8, new[0], new[1]];
+                self.write_status_register(&cmd, is_volatile)?;
+            }
         } else {
-            error!("Failed to read JEDEC ID");
-            Err(Error)
+            if sr1_changed {
+                let cmd = [define::WriteCmd::WrietStatus as u8, new[0]];
+                self.write_status_register(&cmd, is_volatile)?;
+            }
+            if current[1] != new[1] {
+                let cmd = [define::WriteCmd::WriteStatus2 as u8, new[1]];
+                self.write_status_register(&cmd, is_volatile)?;
+            }
         }
+        if current[2] != new[2] {
+            let cmd = [define::WriteCmd::WriteStatus3 as u8, new[2]];
+            self.write_status_register(&cmd, is_volatile)?;
+        }
+        Ok(())
     }
 
-    fn write_enable(&mut self, enable: bool) -> Result<(), Error> {
+    fn write_enable(&mut self, enable: bool) -> Result<(), Error<I::Error>> {
         // Write Enable
         let cmd = if enable {
             [define::WriteCmd::WriteEnable as u8]
@@ -96,79 +369,75 @@ where
             [define::WriteCmd::WriteDisable as u8]
         };
 
-        if self.interface.write(&cmd, None).is_err() {
+        self.interface.write(&cmd, None).map_err(|e| {
             error!("Failed to write enable");
-            return Err(Error);
-        }
-        if let Ok(status) = self.wait_busy() {
-            if enable && (status & define::STATUS::WEL as u8) == 0 {
-                error!("Write enable failed status: {:02X}", status);
-                Err(Error)
-            } else if !enable && (status & define::STATUS::WEL as u8) != 0 {
-                error!("Write disable failed status: {:02X}", status);
-                Err(Error)
-            } else {
-                Ok(())
-            }
+            Error::Interface(e)
+        })?;
+        let status = self
+            .wait_busy(self.flash_info.timeouts.write_status)
+            .inspect_err(|_| {
+                error!("Failed to wait for write enable operation to complete");
+            })?;
+        if enable && (status & define::STATUS::WEL as u8) == 0 {
+            error!("Write enable failed status: {:02X}", status);
+            Err(Error::WriteEnable)
+        } else if !enable && (status & define::STATUS::WEL as u8) != 0 {
+            error!("Write disable failed status: {:02X}", status);
+            Err(Error::WriteEnable)
         } else {
-            error!("Failed to wait for write enable operation to complete");
-            Err(Error)
+            Ok(())
         }
     }
 
-    fn write_operation<F: FnOnce(&mut Self) -> Result<(), Error>>(
+    fn write_operation<F: FnOnce(&mut Self) -> Result<(), Error<I::Error>>>(
         &mut self,
         operation: F,
-    ) -> Result<(), Error> {
-        let ret = if self.write_enable(true).is_ok() {
-            operation(self)
-        } else {
-            Err(Error)
-        };
+    ) -> Result<(), Error<I::Error>> {
+        self.write_enable(true)?;
+        let ret = operation(self);
         let _ = self.write_enable(false);
-        return ret;
-    }
-
-    fn wait_busy(&mut self) -> Result<u8, Error> {
-        // Wait for the flash to be ready
-        let mut is_ok = false;
-        let mut ret_status = 0_u8;
-        for _ in 0..50 {
-            if let Ok(status) = self.read_status() {
-                if (status & define::STATUS::BUSY as u8) == 0 {
-                    is_ok = true;
-                    ret_status = status;
-                    break;
-                } else {
-                    self.interface.delay(10);
-                }
-            } else {
-                return Err(Error);
+        ret
+    }
+
+    /// Poll the status register until BUSY clears or `timeout` microseconds have passed
+    fn wait_busy(&mut self, timeout: u32) -> Result<u8, Error<I::Error>> {
+        // Poll page programs every few microseconds and chip erases every 10 ms
+        let interval = (timeout / 100).clamp(10, 10_000);
+        let mut elapsed = 0_u32;
+        loop {
+            let status = self.read_status()?;
+            if (status & define::STATUS::BUSY as u8) == 0 {
+                return Ok(status);
+            }
+            if elapsed >= timeout {
+                error!("Flash is busy for more than {} us", timeout);
+                return Err(Error::BusyTimeout);
             }
+            self.interface.delay_us(interval);
+            elapsed = elapsed.saturating_add(interval);
         }
+    }
 
-        if is_ok {
-            Ok(ret_status)
-        } else {
-            error!("Flash is busy for too long");
-            Err(Error)
+    pub fn flash_info(&self) -> &FlashInfo {
+        &self.flash_info
+    }
+
+    /// Whether the chip takes the single-line commands of `FlashOperations`
+    fn in_spi_mode(&self) -> bool {
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != OctalMode::Spi {
+            return false;
         }
+        true
     }
 
-    fn set_4byte_address_mode(&mut self) -> Result<(), Error> {
-        // Set 4-byte address mode
-        self.write_operation(|s| {
-            let cmd = if s.enable_address_4_byte {
-                [0xB7]
-            } else {
-                [0xE9]
-            };
-            if s.interface.write(&cmd, None).is_err() {
-                error!("Failed to set 4-byte address mode");
-                return Err(Error);
-            }
-            Ok(())
-        })
+    /// Fail with `Unsupported` while in octal mode, where only the `octal_*` methods work
+    fn check_spi_mode(&self) -> Result<(), Error<I::Error>> {
+        if !self.in_spi_mode() {
+            error!("Only the octal_* methods are supported in octal mode");
+            return Err(Error::Unsupported);
+        }
+        Ok(())
     }
 
     const fn address_len(&self) -> usize {
@@ -177,29 +446,104 @@ where
 
     fn make_address_byte_array(&self, address: u32, buff: &mut [u8]) {
         let len = self.address_len();
-        for i in 0..len {
-            buff[i] = (address >> ((len - (i + 1)) * 8)) as u8;
+        for (i, byte) in buff[..len].iter_mut().enumerate() {
+            *byte = (address >> ((len - (i + 1)) * 8)) as u8;
         }
     }
 
-    fn page_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
-        // Page Program
-        if data.len() > PAGE_SIZE {
-            error!("Data size exceeds {} bytes", PAGE_SIZE);
-            return Err(Error);
+    /// Read `buffer` split at segment boundaries, going back to the first segment after
+    fn read_segments(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error<I::Error>> {
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+
+        let mut offset = 0_usize;
+        while offset < buffer.len() {
+            let addr = address + offset as u32;
+            let len = self.segment_len(addr, buffer.len() - offset);
+            self.read_segment(addr, &mut buffer[offset..offset + len])
+                .inspect_err(|_| {
+                    error!("Failed to read data from address {:08X}", addr);
+                })?;
+            offset += len;
         }
+        self.restore_segment()
+    }
 
+    fn erase_block(&mut self, address: u32, erase_type: EraseType) -> Result<(), Error<I::Error>> {
+        let timeout = self.flash_info.erase_timeout(erase_type.size);
+        // Selecting the segment takes its own write enable
+        let mut cmd = [0_u8; 5];
+        let cmd_len = self.address_command(erase_type.opcode, address, &mut cmd)?;
         self.write_operation(|s| {
-            let mut cmd = [define::WriteCmd::PageProgram as u8, 0, 0, 0, 0];
-            s.make_address_byte_array(address, &mut cmd[1..]);
-            let cmd_len = s.address_len() + 1;
+            s.interface.write(&cmd[..cmd_len], None).map_err(|e| {
+                error!("Failed to erase block at address {:08X}", address);
+                Error::Interface(e)
+            })?;
+            s.wait_busy(timeout).inspect_err(|_| {
+                error!("Failed to wait for erase operation to complete");
+            })?;
+            Ok(())
+        })
+    }
 
-            if s.interface.write(&cmd[..cmd_len], Some(data)).is_err() {
-                return Err(Error);
-            }
-            if s.wait_busy().is_err() {
-                return Err(Error);
-            }
+    fn check_erase(&mut self, address: u32, size: usize) -> Result<(), Error<I::Error>> {
+        self.flash_info.check_erase_alignment(address, size)?;
+        if !self.flash_info.contains(address, size) {
+            return Err(Error::OutOfBounds);
+        }
+        self.check_protection(address, size)
+    }
+
+    /// Split `data` at page boundaries and hand each chunk to `program`
+    fn write_pages<F>(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        program: F,
+    ) -> Result<(), Error<I::Error>>
+    where
+        F: Fn(&mut Self, u32, &[u8]) -> Result<(), Error<I::Error>>,
+    {
+        self.wake_for_access()?;
+        if !self.flash_info.contains(address, data.len()) {
+            error!(
+                "Write out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                data.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+        self.check_protection(address, data.len())?;
+
+        // The first chunk only runs to the end of the current page
+        let page_size = self.flash_info.page_size;
+        let mut offset = 0_usize;
+        while offset < data.len() {
+            let addr = address + offset as u32;
+            let len = (page_size - addr as usize % page_size).min(data.len() - offset);
+            program(self, addr, &data[offset..offset + len]).inspect_err(|_| {
+                error!("Failed to write data to address {:08X}", addr);
+            })?;
+            offset += len;
+        }
+        self.restore_segment()
+    }
+
+    fn page_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        // Page Program
+        if data.len() > self.flash_info.page_size {
+            error!("Data size exceeds {} bytes", self.flash_info.page_size);
+            return Err(Error::OutOfBounds);
+        }
+
+        let mut cmd = [0_u8; 5];
+        let cmd_len =
+            self.address_command(define::WriteCmd::PageProgram as u8, address, &mut cmd)?;
+        self.write_operation(|s| {
+            s.interface
+                .write(&cmd[..cmd_len], Some(data))
+                .map_err(Error::Interface)?;
+            s.wait_busy(s.flash_info.timeouts.page_program)?;
             Ok(())
         })
     }
@@ -209,150 +553,87 @@ impl<I> FlashOperations for Flash<I>
 where
     I: SerialInterface,
 {
-    fn erase_chip(&mut self) -> Result<(), Error> {
+    type Error = Error<I::Error>;
+
+    fn erase_chip(&mut self) -> Result<(), Self::Error> {
+        self.check_spi_mode()?;
+        self.wake_for_access()?;
+        self.check_protection(0, self.flash_info.capacity)?;
         self.write_operation(|s| {
             let cmd = [define::EraseCmd::Chip as u8];
-            s.interface.write(&cmd, None)
+            s.interface.write(&cmd, None).map_err(Error::Interface)?;
+            s.wait_busy(s.flash_info.timeouts.chip_erase)
+                .inspect_err(|_| {
+                    error!("Failed to wait for chip erase to complete");
+                })?;
+            Ok(())
         })
     }
 
-    fn erase(&mut self, address: u32, size: usize) -> Result<(), Error> {
-        assert!(
-            size % self.flash_info.secter_size as usize == 0,
-            "erase_size must be secter_size"
-        );
-        assert!(
-            address % self.flash_info.secter_size == 0,
-            "address must be secter_size aligned"
-        );
-
-        if (address + size as u32) > self.flash_info.capacity as u32 {
-            return Err(Error);
-        }
-        if address == 0 && size == self.flash_info.capacity as usize {
+    fn erase(&mut self, address: u32, size: usize) -> Result<(), Self::Error> {
+        self.check_spi_mode()?;
+        self.wake_for_access()?;
+        self.check_erase(address, size)?;
+        if address == 0 && size == self.flash_info.capacity {
             return self.erase_chip();
         }
 
-        self.write_operation(|s| {
-            let mut size = size;
-            let mut cmd = [define::EraseCmd::Block64k as u8, 0, 0, 0, 0];
-            let mut addr = address;
-            while size > 0 {
-                s.make_address_byte_array(addr, &mut cmd[1..]);
-                let cmd_len = s.address_len() + 1;
-                if s.interface.write(&cmd[..cmd_len], None).is_err() {
-                    error!("Failed to erase block at address {:08X}", addr);
-                    return Err(Error);
-                }
-                if s.wait_busy().is_err() {
-                    error!("Failed to wait for erase operation to complete");
-                    return Err(Error);
-                }
-                if (addr % s.flash_info.secter_size as u32) != 0 {
-                    if size
-                        > s.flash_info.secter_size as usize
-                            - (addr % s.flash_info.secter_size as u32) as usize
-                    {
-                        size -= s.flash_info.secter_size as usize
-                            - (addr % s.flash_info.secter_size as u32) as usize;
-                        addr += s.flash_info.secter_size as u32
-                            - (addr % s.flash_info.secter_size as u32) as u32;
-                    } else {
-                        return Ok(());
-                    }
-                } else {
-                    if size > s.flash_info.secter_size as usize {
-                        size -= s.flash_info.secter_size as usize;
-                        addr += s.flash_info.secter_size as u32;
-                    } else {
-                        return Ok(());
-                    }
-                }
-            }
-            Ok(())
-        })
-    }
-
-    fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
-        let mut data_len = data.len();
-        let mut offset = 0_usize;
-        let get_send_data_len = |len: &mut usize| {
-            if *len > PAGE_SIZE { PAGE_SIZE } else { *len }
-        };
-        loop {
-            let send_data_len = get_send_data_len(&mut data_len);
-            if self
-                .page_write(address + offset as u32, &data[offset..offset + send_data_len])
-                .is_err()
-            {
-                error!(
-                    "Failed to write data to address {:08X}",
-                    address + offset as u32
-                );
-                return Err(Error);
-            }
-
-            offset += send_data_len;
-            data_len -= send_data_len;
+        let erase_types = self.flash_info.erase_types;
 
-            if data_len == 0 {
-                break;
-            }
+        for (addr, erase_type) in ErasePlan::new(&erase_types, address, size) {
+            self.erase_block(addr, erase_type)?;
         }
-        Ok(())
+        self.restore_segment()
     }
 
-    fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error> {
-        if address + buffer.len() as u32 > self.flash_info.capacity as u32 {
+    fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.check_spi_mode()?;
+        self.write_pages(address, data, Self::page_write)
+    }
+
+    fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_spi_mode()?;
+        if !self.flash_info.contains(address, buffer.len()) {
             error!(
                 "Read out of bounds: address {:08X} + size {} > flash size {}",
                 address,
                 buffer.len(),
                 self.flash_info.capacity
             );
-            return Err(Error);
+            return Err(Error::OutOfBounds);
         }
 
-        if self.wait_busy().is_err() {
-            return Err(Error);
+        self.wake_for_access()?;
+        let suspended = self.suspend_for_read(address, buffer.len())?;
+        let read = self.read_segments(address, buffer);
+        // A suspended erase left behind blocks every later write, so resume on failure too
+        if suspended {
+            let resumed = self.resume();
+            read?;
+            return resumed;
         }
-
-        let mut cmd = [define::ReadCmd::Data as u8, 0, 0, 0, 0];
-        self.make_address_byte_array(address, &mut cmd[1..]);
-        let cmd_len = self.address_len() + 1;
-
-        if self
-            .interface
-            .write_and_read(&cmd[..cmd_len], buffer)
-            .is_err()
-        {
-            error!("Failed to read data from address {:08X}", address);
-            return Err(Error);
-        }
-
-        Ok(())
+        read
     }
 
-    fn read_status(&mut self) -> Result<u8, Error> {
-        let mut buff = [0_u8; 1];
-        let cmd = [define::ReadCmd::Status1 as u8];
-
-        if self.interface.write_and_read(&cmd, &mut buff).is_ok() {
-            Ok(buff[0])
-        } else {
-            error!("Failed to read status register");
-            Err(Error)
+    fn read_status(&mut self) -> Result<u8, Self::Error> {
+        self.check_spi_mode()?;
+        self.exit_xip()?;
+        if self.powered_down {
+            self.wake()?;
         }
+        self.read_register(define::ReadCmd::Status1 as u8)
     }
 
-    fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Error> {
-        self.write_operation(|s| {
-            let cmd = [define::WriteCmd::WrietStatus as u8, state];
-            if s.interface.write(&cmd, None).is_err() {
-                error!("Failed to write status register");
-                return Err(Error);
-            }
-            Ok(())
-        })
+    fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Self::Error> {
+        self.check_spi_mode()?;
+        self.wake_for_access()?;
+        // A 1-byte 0x01 clears SR2, and with it QE, on these parts
+        if self.flash_info.quad_enable == QuadEnable::Sr2Bit1 {
+            let sr2 = self.read_register(define::ReadCmd::Status2 as u8)?;
+            let cmd = [define::WriteCmd::WrietStatus as u8, state, sr2];
+            return self.write_status_register(&cmd, is_volatile);
+        }
+        let cmd = [define::WriteCmd::WrietStatus as u8, state];
+        self.write_status_register(&cmd, is_volatile)
     }
 }
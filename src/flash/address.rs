@@ -0,0 +1,126 @@
+use log::error;
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{AddressMode, Error, define};
+
+/// Bytes reachable with 3-byte addresses
+const SEGMENT_SIZE: usize = 1 << 24;
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    /// Put the chip in the addressing method of `FlashInfo`, also after a reset
+    pub(super) fn set_4byte_address_mode(&mut self) -> Result<(), Error<I::Error>> {
+        match self.flash_info.address_mode {
+            AddressMode::Enter4Byte { write_enable } => {
+                let cmd = [define::ModeCmd::Enter4Byte as u8];
+                let enter = |s: &mut Self| {
+                    s.interface.write(&cmd, None).map_err(|e| {
+                        error!("Failed to enter 4-byte address mode");
+                        Error::Interface(e)
+                    })
+                };
+                if write_enable {
+                    self.write_operation(enter)
+                } else {
+                    enter(self)
+                }
+            }
+            AddressMode::Always4Byte => Ok(()),
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                // The register content is unknown after a reset or a previous run
+                self.address_segment = u8::MAX;
+                self.select_segment(0)
+            }
+            AddressMode::ThreeByte | AddressMode::FourByteOpcodes => {
+                // A previous run may have left the chip in 4-byte mode
+                self.write_operation(|s| {
+                    let cmd = [define::ModeCmd::Exit4Byte as u8];
+                    s.interface.write(&cmd, None).map_err(|e| {
+                        error!("Failed to exit 4-byte address mode");
+                        Error::Interface(e)
+                    })
+                })
+            }
+        }
+    }
+
+    /// Opcode and address length of a read, program or erase at `address`
+    ///
+    /// Selects the 16M segment first when the extended address or bank register is in use.
+    pub(super) fn address_opcode(
+        &mut self,
+        opcode: u8,
+        address: u32,
+    ) -> Result<(u8, usize), Error<I::Error>> {
+        match self.flash_info.address_mode {
+            AddressMode::FourByteOpcodes => match define::four_byte_opcode(opcode) {
+                Some(opcode) => Ok((opcode, 4)),
+                // The chip stays in 3-byte mode, e.g. for the block lock commands
+                None if (address as usize) < SEGMENT_SIZE => Ok((opcode, 3)),
+                None => {
+                    error!("No 4-byte opcode for {:02X}", opcode);
+                    Err(Error::Unsupported)
+                }
+            },
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                self.select_segment((address >> 24) as u8)?;
+                Ok((opcode, 3))
+            }
+            _ => Ok((opcode, self.address_len())),
+        }
+    }
+
+    /// Fill `cmd` with the opcode and address of a read, program or erase, returning its length
+    pub(super) fn address_command(
+        &mut self,
+        opcode: u8,
+        address: u32,
+        cmd: &mut [u8; 5],
+    ) -> Result<usize, Error<I::Error>> {
+        let (opcode, len) = self.address_opcode(opcode, address)?;
+        cmd[0] = opcode;
+        for (i, byte) in cmd[1..=len].iter_mut().enumerate() {
+            *byte = (address >> ((len - (i + 1)) * 8)) as u8;
+        }
+        Ok(len + 1)
+    }
+
+    /// Bytes from `address` that one command can read, reads must not cross a segment
+    pub(super) fn segment_len(&self, address: u32, len: usize) -> usize {
+        match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => {
+                len.min(SEGMENT_SIZE - address as usize % SEGMENT_SIZE)
+            }
+            _ => len,
+        }
+    }
+
+    fn select_segment(&mut self, segment: u8) -> Result<(), Error<I::Error>> {
+        if self.address_segment == segment {
+            return Ok(());
+        }
+        let opcode = match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress => define::ModeCmd::WriteExtendedAddress as u8,
+            _ => define::ModeCmd::WriteBank as u8,
+        };
+        self.write_operation(|s| {
+            s.interface.write(&[opcode, segment], None).map_err(|e| {
+                error!("Failed to select address segment {}", segment);
+                Error::Interface(e)
+            })
+        })?;
+        self.address_segment = segment;
+        Ok(())
+    }
+
+    /// Go back to the first segment, where a 3-byte boot ROM expects it
+    pub(super) fn restore_segment(&mut self) -> Result<(), Error<I::Error>> {
+        match self.flash_info.address_mode {
+            AddressMode::ExtendedAddress | AddressMode::BankRegister => self.select_segment(0),
+            _ => Ok(()),
+        }
+    }
+}
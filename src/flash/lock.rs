@@ -0,0 +1,167 @@
+use core::ops::Range;
+
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, define};
+
+const SECTOR_SIZE: u32 = 4 * 1024;
+const BLOCK_SIZE: u32 = 64 * 1024;
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    /// Switch between the BP bits and the individual block locks (WPS)
+    ///
+    /// Every block powers up locked, so after enabling the locks the blocks to be
+    /// written must be unlocked first, e.g. with `unlock_all`.
+    pub fn set_block_locks(
+        &mut self,
+        enable: bool,
+        is_volatile: bool,
+    ) -> Result<(), Error<I::Error>> {
+        let mut sr = self.read_status_registers()?;
+        sr.set_write_protect_selection(enable);
+        self.write_status_registers(&sr, is_volatile)?;
+        if self.block_locks != enable {
+            error!("Status register is locked, WPS unchanged");
+            return Err(Error::WriteProtected);
+        }
+        info!(
+            "Block locks {}",
+            if enable { "enabled" } else { "disabled" }
+        );
+        Ok(())
+    }
+
+    /// Range covered by the lock bit of `address`
+    ///
+    /// The lowest and highest 64K blocks are locked per 4K sector, every other one as a whole.
+    pub fn lock_unit(&self, address: u32) -> Range<u32> {
+        let capacity = self.flash_info.capacity as u32;
+        let size = if address < BLOCK_SIZE || address >= capacity.saturating_sub(BLOCK_SIZE) {
+            SECTOR_SIZE
+        } else {
+            BLOCK_SIZE
+        };
+        let start = address - address % size;
+        start..start + size
+    }
+
+    /// Lock the block or sector containing `address`
+    pub fn lock_block(&mut self, address: u32) -> Result<(), Error<I::Error>> {
+        self.block_lock_command(define::LockCmd::BlockLock as u8, address)
+    }
+
+    /// Unlock the block or sector containing `address`
+    pub fn unlock_block(&mut self, address: u32) -> Result<(), Error<I::Error>> {
+        self.block_lock_command(define::LockCmd::BlockUnlock as u8, address)
+    }
+
+    pub fn is_block_locked(&mut self, address: u32) -> Result<bool, Error<I::Error>> {
+        self.check_lock_address(address)?;
+        self.wake_for_access()?;
+        let locked = self.read_block_lock(address)?;
+        self.restore_segment()?;
+        Ok(locked)
+    }
+
+    /// Lock every block and sector
+    pub fn lock_all(&mut self) -> Result<(), Error<I::Error>> {
+        self.global_lock_command(define::LockCmd::GlobalLock as u8)
+    }
+
+    /// Unlock every block and sector
+    pub fn unlock_all(&mut self) -> Result<(), Error<I::Error>> {
+        self.global_lock_command(define::LockCmd::GlobalUnlock as u8)
+    }
+
+    /// Fail with `WriteProtected` if a block in `address..address + len` is locked
+    pub(super) fn check_block_locks(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<(), Error<I::Error>> {
+        // The lock commands are only decoded in SPI mode
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            return Ok(());
+        }
+
+        let end = address as usize + len;
+        let mut unit = self.lock_unit(address);
+        while (unit.start as usize) < end {
+            if self.read_block_lock(unit.start)? {
+                error!(
+                    "{:08X} + {} overlaps locked block {:08X}..{:08X}",
+                    address, len, unit.start, unit.end
+                );
+                return Err(Error::WriteProtected);
+            }
+            if unit.end as usize >= self.flash_info.capacity {
+                break;
+            }
+            unit = self.lock_unit(unit.end);
+        }
+        self.restore_segment()
+    }
+
+    fn read_block_lock(&mut self, address: u32) -> Result<bool, Error<I::Error>> {
+        let mut cmd = [0_u8; 5];
+        let cmd_len =
+            self.address_command(define::LockCmd::ReadBlockLock as u8, address, &mut cmd)?;
+        let mut buff = [0_u8; 1];
+        self.interface
+            .write_and_read(&cmd[..cmd_len], &mut buff)
+            .map_err(|e| {
+                error!("Failed to read block lock at address {:08X}", address);
+                Error::Interface(e)
+            })?;
+        Ok(buff[0] & 0x01 != 0)
+    }
+
+    fn check_lock_address(&self, address: u32) -> Result<(), Error<I::Error>> {
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            error!("Block locks are only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+        if address as usize >= self.flash_info.capacity {
+            error!("Address {:08X} out of bounds", address);
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    fn block_lock_command(&mut self, opcode: u8, address: u32) -> Result<(), Error<I::Error>> {
+        self.check_lock_address(address)?;
+        self.wake_for_access()?;
+        // Selecting the segment takes its own write enable
+        let mut cmd = [0_u8; 5];
+        let cmd_len = self.address_command(opcode, address, &mut cmd)?;
+        self.write_operation(|s| {
+            s.interface.write(&cmd[..cmd_len], None).map_err(|e| {
+                error!("Failed to change block lock at address {:08X}", address);
+                Error::Interface(e)
+            })?;
+            s.wait_busy(s.flash_info.timeouts.write_status)?;
+            Ok(())
+        })?;
+        self.restore_segment()
+    }
+
+    fn global_lock_command(&mut self, opcode: u8) -> Result<(), Error<I::Error>> {
+        self.check_lock_address(0)?;
+        self.wake_for_access()?;
+        self.write_operation(|s| {
+            s.interface.write(&[opcode], None).map_err(|e| {
+                error!("Failed to change global block lock");
+                Error::Interface(e)
+            })?;
+            s.wait_busy(s.flash_info.timeouts.write_status)?;
+            Ok(())
+        })
+    }
+}
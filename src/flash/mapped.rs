@@ -0,0 +1,109 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::{BusWidth, MemoryMappedInterface, QuadCommand, SerialInterface};
+use crate::{Error, ReadCommand};
+
+/// Leaves memory-mapped mode, kept by `Flash` so every operation can go back to indirect mode
+pub(super) type ExitMapped<I> = fn(&mut I) -> Result<(), <I as SerialInterface>::Error>;
+
+impl<I> Flash<I>
+where
+    I: MemoryMappedInterface,
+{
+    pub fn is_memory_mapped(&self) -> bool {
+        self.memory_mapped.is_some()
+    }
+
+    /// Fastest read of the current quad and read modes, with its opcode, address and data widths
+    fn mapped_read(&self) -> Result<(ReadCommand, [BusWidth; 3]), Error<I::Error>> {
+        #[cfg(feature = "qspi")]
+        {
+            use super::QuadMode;
+            let (read, widths) = match self.quad_mode {
+                QuadMode::Single => (None, [BusWidth::Single; 3]),
+                QuadMode::QuadOutput => (
+                    self.flash_info.quad_output_read,
+                    [BusWidth::Single, BusWidth::Single, BusWidth::Quad],
+                ),
+                QuadMode::QuadIo => (
+                    self.flash_info.quad_io_read,
+                    [BusWidth::Single, BusWidth::Quad, BusWidth::Quad],
+                ),
+                QuadMode::Qpi => (self.flash_info.qpi_read, [BusWidth::Quad; 3]),
+            };
+            if let Some(read) = read {
+                return Ok((read, widths));
+            }
+        }
+        let (read, address_width, data_width) = self.mode_read()?;
+        Ok((read, [BusWidth::Single, address_width, data_width]))
+    }
+
+    /// Read command template for a memory-mapped controller, derived from the part and modes
+    ///
+    /// With `xip` set the mode bits keep the chip in continuous read mode, for controllers
+    /// that send the opcode only once. In the extended address and bank register modes
+    /// only the first 16M are reachable.
+    pub fn memory_mapped_command(&mut self, xip: bool) -> Result<QuadCommand, Error<I::Error>> {
+        let mut cmd = if xip {
+            self.xip_command()?
+        } else {
+            let (read, [opcode_width, address_width, data_width]) = self.mapped_read()?;
+            self.read_command(read, 0, opcode_width, address_width, data_width)?
+        };
+        cmd.address = None;
+        Ok(cmd)
+    }
+
+    /// Hand the chip to the controller for memory-mapped reads
+    ///
+    /// Any other operation on this `Flash` switches back to indirect mode first.
+    pub fn enter_memory_mapped(&mut self, xip: bool) -> Result<(), Error<I::Error>> {
+        if self.memory_mapped.is_some() {
+            return Ok(());
+        }
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            error!("Memory-mapped mode is only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+        self.wake_for_access()?;
+        let cmd = self.memory_mapped_command(xip)?;
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+
+        self.interface.enter_memory_mapped(&cmd).map_err(|e| {
+            error!("Failed to enter memory-mapped mode");
+            Error::Interface(e)
+        })?;
+        self.memory_mapped = Some(I::exit_memory_mapped);
+        // The first mapped read arms continuous read mode
+        self.xip = xip;
+        info!("Memory-mapped with {:02X}", cmd.opcode);
+        Ok(())
+    }
+
+    /// Switch the controller back to indirect mode, leaving XIP too
+    pub fn exit_memory_mapped(&mut self) -> Result<(), Error<I::Error>> {
+        self.leave_memory_mapped()?;
+        self.exit_xip()
+    }
+}
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    pub(super) fn leave_memory_mapped(&mut self) -> Result<(), Error<I::Error>> {
+        let Some(exit) = self.memory_mapped else {
+            return Ok(());
+        };
+        exit(&mut self.interface).map_err(|e| {
+            error!("Failed to exit memory-mapped mode");
+            Error::Interface(e)
+        })?;
+        self.memory_mapped = None;
+        info!("Left memory-mapped mode");
+        Ok(())
+    }
+}
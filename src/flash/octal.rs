@@ -0,0 +1,290 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::erase::ErasePlan;
+use crate::serial_interface::{OctalCommand, OctalSerialInterface};
+use crate::{CommandExtension, Error, FlashOperations, OctalEnable, OctalInfo, define};
+
+/// Protocol used by the `octal_*` methods
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OctalMode {
+    /// Plain 1-1-1 SPI
+    Spi,
+    /// 8S-8S-8S, single transfer rate
+    Str,
+    /// 8D-8D-8D, double transfer rate
+    Dtr,
+}
+
+impl<I> Flash<I>
+where
+    I: OctalSerialInterface,
+{
+    pub fn octal_mode(&self) -> OctalMode {
+        self.octal_mode
+    }
+
+    fn octal_info(&self) -> Result<OctalInfo, Error<I::Error>> {
+        self.flash_info.octal.ok_or_else(|| {
+            error!("Octal mode is not supported by this flash");
+            Error::Unsupported
+        })
+    }
+
+    fn octal_command(
+        &self,
+        octal: &OctalInfo,
+        opcode: u8,
+        address: Option<u32>,
+        dummy_cycles: u8,
+    ) -> OctalCommand {
+        OctalCommand {
+            opcode,
+            extension: Some(match octal.extension {
+                CommandExtension::Repeat => opcode,
+                CommandExtension::Invert => !opcode,
+            }),
+            address,
+            dummy_cycles,
+            dtr: self.octal_mode == OctalMode::Dtr,
+        }
+    }
+
+    /// Switch the chip between SPI and octal mode through its configuration register
+    ///
+    /// While in octal mode only the `octal_*` methods may be used.
+    pub fn set_octal_mode(&mut self, mode: OctalMode) -> Result<(), Error<I::Error>> {
+        if mode == self.octal_mode {
+            return Ok(());
+        }
+        let octal = self.octal_info()?;
+        self.wake_for_access()?;
+
+        if self.octal_mode != OctalMode::Spi {
+            self.exit_octal(&octal)?;
+        }
+        if mode != OctalMode::Spi {
+            self.enter_octal(&octal, mode)?;
+        }
+
+        info!("Octal mode: {:?}", mode);
+        Ok(())
+    }
+
+    fn enter_octal(&mut self, octal: &OctalInfo, mode: OctalMode) -> Result<(), Error<I::Error>> {
+        let (dummy_cmd, mode_cmd, cmd_len) = match octal.enable {
+            OctalEnable::Macronix => {
+                // CR2 0x300 holds the dummy cycle code, 0 selects 20 cycles
+                let code = 20_u8.saturating_sub(octal.dummy_cycles).min(14) / 2;
+                let value = if mode == OctalMode::Dtr { 0x02 } else { 0x01 };
+                (
+                    [define::OctalCmd::WriteCr2 as u8, 0, 0, 0x03, 0x00, code],
+                    [define::OctalCmd::WriteCr2 as u8, 0, 0, 0, 0, value],
+                    6,
+                )
+            }
+            OctalEnable::Micron => {
+                let value = if mode == OctalMode::Dtr { 0xE7 } else { 0xB7 };
+                let len = self.address_len();
+                let mut dummy_cmd = [define::OctalCmd::WriteVcr as u8, 0, 0, 0, 0, 0];
+                let mut mode_cmd = dummy_cmd;
+                self.make_address_byte_array(0x01, &mut dummy_cmd[1..]);
+                dummy_cmd[len + 1] = octal.dummy_cycles;
+                mode_cmd[len + 1] = value;
+                (dummy_cmd, mode_cmd, len + 2)
+            }
+        };
+
+        self.write_operation(|s| {
+            s.interface
+                .write(&dummy_cmd[..cmd_len], None)
+                .map_err(Error::Interface)?;
+            s.wait_busy(s.flash_info.timeouts.write_status)?;
+            Ok(())
+        })?;
+
+        // The chip answers in octal mode right after this write, so skip the SPI write disable
+        self.write_enable(true)?;
+        self.interface
+            .write(&mode_cmd[..cmd_len], None)
+            .map_err(|e| {
+                error!("Failed to enter octal mode");
+                Error::Interface(e)
+            })?;
+        self.octal_mode = mode;
+        self.octal_wait_busy(octal, self.flash_info.timeouts.write_status)?;
+        Ok(())
+    }
+
+    fn exit_octal(&mut self, octal: &OctalInfo) -> Result<(), Error<I::Error>> {
+        let (opcode, value) = match octal.enable {
+            OctalEnable::Macronix => (define::OctalCmd::WriteCr2 as u8, 0x00),
+            OctalEnable::Micron => (define::OctalCmd::WriteVcr as u8, 0xFF),
+        };
+        // DTR transfers come in pairs of bytes
+        let data = [value; 2];
+        let data_len = if self.octal_mode == OctalMode::Dtr {
+            2
+        } else {
+            1
+        };
+
+        self.octal_write_enable(octal)?;
+        let cmd = self.octal_command(octal, opcode, Some(0), 0);
+        self.interface
+            .octal_write(&cmd, Some(&data[..data_len]))
+            .map_err(|e| {
+                error!("Failed to exit octal mode");
+                Error::Interface(e)
+            })?;
+        self.octal_mode = OctalMode::Spi;
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+        Ok(())
+    }
+
+    fn octal_read_status(&mut self, octal: &OctalInfo) -> Result<u8, Error<I::Error>> {
+        let address = octal.status_address.then_some(0);
+        let cmd = self.octal_command(
+            octal,
+            define::ReadCmd::Status1 as u8,
+            address,
+            octal.status_dummy_cycles,
+        );
+        let mut buff = [0_u8; 2];
+        let len = if cmd.dtr { 2 } else { 1 };
+        self.interface
+            .octal_read(&cmd, &mut buff[..len])
+            .map_err(|e| {
+                error!("Failed to read status register");
+                Error::Interface(e)
+            })?;
+        Ok(buff[0])
+    }
+
+    fn octal_wait_busy(&mut self, octal: &OctalInfo, timeout: u32) -> Result<u8, Error<I::Error>> {
+        let interval = (timeout / 100).clamp(10, 10_000);
+        let mut elapsed = 0_u32;
+        loop {
+            let status = self.octal_read_status(octal)?;
+            if (status & define::STATUS::BUSY as u8) == 0 {
+                return Ok(status);
+            }
+            if elapsed >= timeout {
+                error!("Flash is busy for more than {} us", timeout);
+                return Err(Error::BusyTimeout);
+            }
+            self.interface.delay_us(interval);
+            elapsed = elapsed.saturating_add(interval);
+        }
+    }
+
+    fn octal_write_enable(&mut self, octal: &OctalInfo) -> Result<(), Error<I::Error>> {
+        let cmd = self.octal_command(octal, define::WriteCmd::WriteEnable as u8, None, 0);
+        self.interface.octal_write(&cmd, None).map_err(|e| {
+            error!("Failed to write enable");
+            Error::Interface(e)
+        })?;
+        let status = self.octal_wait_busy(octal, self.flash_info.timeouts.write_status)?;
+        if (status & define::STATUS::WEL as u8) == 0 {
+            error!("Write enable failed status: {:02X}", status);
+            return Err(Error::WriteEnable);
+        }
+        Ok(())
+    }
+
+    /// Send a command that needs the write enable latch and wait for it to finish
+    fn octal_write_command(
+        &mut self,
+        octal: &OctalInfo,
+        opcode: u8,
+        address: u32,
+        data: Option<&[u8]>,
+        timeout: u32,
+    ) -> Result<(), Error<I::Error>> {
+        self.octal_write_enable(octal)?;
+        let cmd = self.octal_command(octal, opcode, Some(address), 0);
+        self.interface
+            .octal_write(&cmd, data)
+            .map_err(Error::Interface)?;
+        self.octal_wait_busy(octal, timeout)?;
+        Ok(())
+    }
+
+    pub fn octal_read_data(
+        &mut self,
+        address: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        if self.octal_mode == OctalMode::Spi {
+            return self.read_data(address, buffer);
+        }
+        let octal = self.octal_info()?;
+        self.wake_for_access()?;
+
+        if !self.flash_info.contains(address, buffer.len()) {
+            error!(
+                "Read out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                buffer.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+
+        self.octal_wait_busy(&octal, self.flash_info.timeouts.write_status)?;
+
+        let cmd = self.octal_command(&octal, octal.read_opcode, Some(address), octal.dummy_cycles);
+        self.interface.octal_read(&cmd, buffer).map_err(|e| {
+            error!("Failed to read data from address {:08X}", address);
+            Error::Interface(e)
+        })
+    }
+
+    pub fn octal_write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        if self.octal_mode == OctalMode::Spi {
+            return self.write_data(address, data);
+        }
+        self.write_pages(address, data, Self::octal_page_write)
+    }
+
+    fn octal_page_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        if data.len() > self.flash_info.page_size {
+            error!("Data size exceeds {} bytes", self.flash_info.page_size);
+            return Err(Error::OutOfBounds);
+        }
+        let octal = self.octal_info()?;
+        self.octal_write_command(
+            &octal,
+            define::FourByteCmd::PageProgram as u8,
+            address,
+            Some(data),
+            self.flash_info.timeouts.page_program,
+        )
+    }
+
+    pub fn octal_erase(&mut self, address: u32, size: usize) -> Result<(), Error<I::Error>> {
+        if self.octal_mode == OctalMode::Spi {
+            return self.erase(address, size);
+        }
+        let octal = self.octal_info()?;
+        self.wake_for_access()?;
+        self.check_erase(address, size)?;
+
+        let erase_types = self.flash_info.erase_types;
+        for (addr, erase_type) in ErasePlan::new(&erase_types, address, size) {
+            // Octal mode always uses 4-byte addresses
+            let opcode = match erase_type.size {
+                0x1000 => define::FourByteCmd::Sector4k as u8,
+                0x8000 => define::FourByteCmd::Block32k as u8,
+                0x10000 => define::FourByteCmd::Block64k as u8,
+                _ => return Err(Error::Unsupported),
+            };
+            let timeout = self.flash_info.erase_timeout(erase_type.size);
+            self.octal_write_command(&octal, opcode, addr, None, timeout)
+                .inspect_err(|_| {
+                    error!("Failed to erase block at address {:08X}", addr);
+                })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, PowerDownInfo};
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    fn power_down_info(&self) -> Result<PowerDownInfo, Error<I::Error>> {
+        self.flash_info.power_down.ok_or_else(|| {
+            error!("Deep power-down is not supported by this flash");
+            Error::Unsupported
+        })
+    }
+
+    pub fn is_powered_down(&self) -> bool {
+        self.powered_down
+    }
+
+    /// Enter deep power-down, the chip then ignores every command but `wake`
+    pub fn power_down(&mut self) -> Result<(), Error<I::Error>> {
+        let info = self.power_down_info()?;
+        if self.powered_down {
+            return Ok(());
+        }
+        self.check_spi_mode()?;
+
+        self.exit_xip()?;
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+        self.interface.write(&[info.enter], None).map_err(|e| {
+            error!("Failed to enter deep power-down");
+            Error::Interface(e)
+        })?;
+        self.interface.delay_us(info.enter_delay);
+        self.powered_down = true;
+        info!("Powered down");
+        Ok(())
+    }
+
+    /// Leave deep power-down
+    pub fn wake(&mut self) -> Result<(), Error<I::Error>> {
+        let info = self.power_down_info()?;
+        if !self.powered_down {
+            return Ok(());
+        }
+
+        self.interface.write(&[info.exit], None).map_err(|e| {
+            error!("Failed to leave deep power-down");
+            Error::Interface(e)
+        })?;
+        self.interface.delay_us(info.exit_delay);
+        self.powered_down = false;
+        self.idle_ms = 0;
+        info!("Woken up");
+        Ok(())
+    }
+
+    /// Power down after `idle_ms` milliseconds without an operation, counted by `tick`
+    pub fn set_auto_power_down(&mut self, idle_ms: Option<u32>) -> Result<(), Error<I::Error>> {
+        if idle_ms.is_some() {
+            self.power_down_info()?;
+        }
+        self.auto_power_down = idle_ms;
+        self.idle_ms = 0;
+        Ok(())
+    }
+
+    /// Advance the idle time by `elapsed_ms`, powering down once the auto power-down period is reached
+    ///
+    /// Call this periodically, e.g. from a timer task. A program or erase still running
+    /// postpones the power-down to a later tick.
+    pub fn tick(&mut self, elapsed_ms: u32) -> Result<(), Error<I::Error>> {
+        let Some(limit) = self.auto_power_down else {
+            return Ok(());
+        };
+        // A memory-mapped controller may be reading the chip, and power-down is SPI only
+        if self.powered_down || self.xip || self.memory_mapped.is_some() || !self.in_spi_mode() {
+            return Ok(());
+        }
+
+        self.idle_ms = self.idle_ms.saturating_add(elapsed_ms);
+        if self.idle_ms < limit || self.is_busy()? {
+            return Ok(());
+        }
+        self.power_down()
+    }
+
+    /// Wake the chip or leave XIP if needed and restart the idle period, called by every operation
+    pub(super) fn wake_for_access(&mut self) -> Result<(), Error<I::Error>> {
+        self.idle_ms = 0;
+        // A chip in XIP was not powered down, and would take the wake opcode as an address
+        self.exit_xip()?;
+        if self.powered_down {
+            self.wake()?;
+        }
+        Ok(())
+    }
+}
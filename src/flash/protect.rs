@@ -0,0 +1,342 @@
+use core::ops::Range;
+
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::status::StatusRegisters;
+use crate::{Error, FlashInfo};
+
+/// BP table of the Winbond layout, which depends on the capacity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Layout {
+    /// 4 to 16 MiB: BP2:0 in fractions of the chip, or 4K to 32K with SEC set
+    Fraction,
+    /// 32 and 64 MiB Winbond: BP3:0 in 64K steps, TB in SR1 bit 6 and no SEC
+    Blocks,
+}
+
+impl Layout {
+    /// `None` for tables that are not modelled, like the 64K steps of the W25Q80 and W25Q16
+    fn of(info: &FlashInfo) -> Option<Self> {
+        if !info.winbond_status_layout() {
+            return None;
+        }
+        match (info.manufacturer_id, info.capacity) {
+            (_, 0x40_0000 | 0x80_0000 | 0x100_0000) => Some(Layout::Fraction),
+            (0xEF, 0x200_0000 | 0x400_0000) => Some(Layout::Blocks),
+            _ => None,
+        }
+    }
+}
+
+/// One BP, TB, SEC and CMP combination
+#[derive(Clone, Copy)]
+struct BlockProtect {
+    layout: Layout,
+    bp: u8,
+    top_bottom: bool,
+    sector: bool,
+    complement: bool,
+}
+
+impl BlockProtect {
+    fn from_status(sr: &StatusRegisters, layout: Layout) -> Self {
+        let (bp, top_bottom, sector) = match layout {
+            Layout::Fraction => (sr.block_protect(), sr.top_bottom(), sr.sector_protect()),
+            Layout::Blocks => (sr.wide_block_protect(), sr.wide_top_bottom(), false),
+        };
+        BlockProtect {
+            layout,
+            bp,
+            top_bottom,
+            sector,
+            complement: sr.complement_protect(),
+        }
+    }
+
+    fn apply(&self, sr: &mut StatusRegisters) {
+        match self.layout {
+            Layout::Fraction => {
+                sr.set_block_protect(self.bp);
+                sr.set_top_bottom(self.top_bottom);
+                sr.set_sector_protect(self.sector);
+            }
+            Layout::Blocks => {
+                sr.set_wide_block_protect(self.bp);
+                sr.set_wide_top_bottom(self.top_bottom);
+            }
+        }
+        sr.set_complement_protect(self.complement);
+    }
+
+    /// Protected range, `None` if nothing is protected
+    ///
+    /// With `Fraction`, BP 1 to 6 protect 1/64 to 1/2 of the chip, or 4K to 32K with SEC set.
+    /// With `Blocks`, BP 1 protects 64K and every step doubles it up to the whole chip.
+    fn range(&self, capacity: u32) -> Option<Range<u32>> {
+        let size = match (self.layout, self.bp) {
+            (_, 0) => 0,
+            (Layout::Fraction, 7) => capacity,
+            (Layout::Fraction, bp) if self.sector => (4096 << (bp - 1).min(3)).min(capacity),
+            (Layout::Fraction, bp) => capacity >> (7 - bp),
+            (Layout::Blocks, bp) => (0x1_0000 << (bp - 1)).min(capacity),
+        };
+        let range = match (self.top_bottom, self.complement) {
+            (false, false) => capacity - size..capacity,
+            (true, false) => 0..size,
+            (false, true) => 0..capacity - size,
+            (true, true) => size..capacity,
+        };
+        (!range.is_empty()).then_some(range)
+    }
+
+    fn all(layout: Layout) -> impl Iterator<Item = Self> {
+        (0..64_u8).map(move |i| match layout {
+            Layout::Fraction => BlockProtect {
+                layout,
+                bp: i & 0b111,
+                top_bottom: i & 0b1000 != 0,
+                sector: i & 0b1_0000 != 0,
+                complement: i & 0b10_0000 != 0,
+            },
+            Layout::Blocks => BlockProtect {
+                layout,
+                bp: i & 0b1111,
+                top_bottom: i & 0b1_0000 != 0,
+                sector: false,
+                complement: i & 0b10_0000 != 0,
+            },
+        })
+    }
+}
+
+fn len(range: &Option<Range<u32>>) -> u32 {
+    range.as_ref().map_or(0, |r| r.len() as u32)
+}
+
+fn contains(outer: &Option<Range<u32>>, inner: &Range<u32>) -> bool {
+    outer
+        .as_ref()
+        .is_some_and(|o| o.start <= inner.start && inner.end <= o.end)
+}
+
+fn overlaps(a: &Range<u32>, b: &Range<u32>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    /// Reload the protected range after a status register write
+    ///
+    /// Parts without a modelled BP table are not tracked, the chip still rejects
+    /// writes to its protected area.
+    pub(super) fn refresh_protection(&mut self) -> Result<(), Error<I::Error>> {
+        if !self.flash_info.winbond_status_layout() {
+            self.block_locks = false;
+            self.protected = None;
+            return Ok(());
+        }
+        let sr = self.read_status_registers()?;
+        // With WPS set the BP bits are ignored in favour of the individual block locks
+        self.block_locks = sr.write_protect_selection();
+        self.protected = match Layout::of(&self.flash_info) {
+            Some(layout) if !self.block_locks => {
+                BlockProtect::from_status(&sr, layout).range(self.flash_info.capacity as u32)
+            }
+            _ => None,
+        };
+        Ok(())
+    }
+
+    /// Fail with `WriteProtected` if `address..address + len` overlaps the protected range
+    /// or a locked block
+    pub(super) fn check_protection(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<(), Error<I::Error>> {
+        if self.block_locks {
+            return self.check_block_locks(address, len);
+        }
+        let Some(protected) = &self.protected else {
+            return Ok(());
+        };
+        let range = address..address + len as u32;
+        if !range.is_empty() && overlaps(protected, &range) {
+            error!(
+                "{:08X} + {} overlaps protected range {:08X}..{:08X}",
+                address, len, protected.start, protected.end
+            );
+            return Err(Error::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// Range protected by the BP bits
+    ///
+    /// The BP scheme only ever protects one contiguous range, `None` if nothing is protected.
+    pub fn protected_ranges(&mut self) -> Result<Option<Range<u32>>, Error<I::Error>> {
+        self.block_protect_layout()?;
+        self.refresh_protection()?;
+        Ok(self.protected.clone())
+    }
+
+    /// Protect `range` on top of what is already protected
+    ///
+    /// Picks the smallest BP combination covering both, which may protect more than asked.
+    pub fn protect(&mut self, range: Range<u32>) -> Result<(), Error<I::Error>> {
+        let layout = self.block_protect_layout()?;
+        self.check_range(&range)?;
+        self.refresh_protection()?;
+
+        let target = match &self.protected {
+            Some(p) => p.start.min(range.start)..p.end.max(range.end),
+            None => range,
+        };
+        let capacity = self.flash_info.capacity as u32;
+        let Some(choice) = BlockProtect::all(layout)
+            .filter(|bp| contains(&bp.range(capacity), &target))
+            .min_by_key(|bp| len(&bp.range(capacity)))
+        else {
+            return Err(Error::Unsupported);
+        };
+        self.write_block_protect(choice)
+    }
+
+    /// Remove protection from `range`
+    ///
+    /// Keeps the largest BP combination inside the current protection that leaves
+    /// `range` writable, which may unprotect more than asked.
+    pub fn unprotect(&mut self, range: Range<u32>) -> Result<(), Error<I::Error>> {
+        let layout = self.block_protect_layout()?;
+        self.check_range(&range)?;
+        self.refresh_protection()?;
+
+        let capacity = self.flash_info.capacity as u32;
+        let current = self.protected.clone();
+        let choice = BlockProtect::all(layout)
+            .filter(|bp| match bp.range(capacity) {
+                Some(r) => contains(&current, &r) && !overlaps(&r, &range),
+                None => true,
+            })
+            .max_by_key(|bp| len(&bp.range(capacity)));
+        let Some(choice) = choice else {
+            return Err(Error::Unsupported);
+        };
+        self.write_block_protect(choice)
+    }
+
+    /// The BP table is only known for some sizes of the Winbond layout
+    fn block_protect_layout(&self) -> Result<Layout, Error<I::Error>> {
+        Layout::of(&self.flash_info).ok_or_else(|| {
+            error!(
+                "Block protection of manufacturer {:02X} with {} bytes is not supported",
+                self.flash_info.manufacturer_id, self.flash_info.capacity
+            );
+            Error::Unsupported
+        })
+    }
+
+    fn check_range(&self, range: &Range<u32>) -> Result<(), Error<I::Error>> {
+        if range.is_empty() || range.end as usize > self.flash_info.capacity {
+            error!("Invalid range {:08X}..{:08X}", range.start, range.end);
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    fn write_block_protect(&mut self, choice: BlockProtect) -> Result<(), Error<I::Error>> {
+        let mut sr = self.read_status_registers()?;
+        if sr.write_protect_selection() {
+            error!("BP bits are ignored while WPS is set");
+            return Err(Error::Unsupported);
+        }
+        choice.apply(&mut sr);
+        self.write_status_registers(&sr, false)?;
+
+        // SRP and the /WP pin can lock the status register
+        let expected = choice.range(self.flash_info.capacity as u32);
+        if self.protected != expected {
+            error!("Status register is locked, protection unchanged");
+            return Err(Error::WriteProtected);
+        }
+        match &self.protected {
+            Some(p) => info!("Protected {:08X}..{:08X}", p.start, p.end),
+            None => info!("Nothing protected"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPACITY: u32 = 16 * 1024 * 1024;
+
+    fn range(bp: u8, top_bottom: bool, sector: bool, complement: bool) -> Option<Range<u32>> {
+        BlockProtect {
+            layout: Layout::Fraction,
+            bp,
+            top_bottom,
+            sector,
+            complement,
+        }
+        .range(CAPACITY)
+    }
+
+    #[test]
+    fn fractions_of_the_chip() {
+        assert_eq!(range(0, false, false, false), None);
+        assert_eq!(
+            range(1, false, false, false),
+            Some(CAPACITY - 0x4_0000..CAPACITY)
+        );
+        assert_eq!(range(6, false, false, false), Some(CAPACITY / 2..CAPACITY));
+        assert_eq!(range(1, true, false, false), Some(0..0x4_0000));
+        assert_eq!(range(7, false, false, false), Some(0..CAPACITY));
+    }
+
+    #[test]
+    fn sectors() {
+        assert_eq!(
+            range(1, false, true, false),
+            Some(CAPACITY - 0x1000..CAPACITY)
+        );
+        assert_eq!(range(3, true, true, false), Some(0..0x4000));
+        // BP 4 and 5 stay at 32K
+        assert_eq!(range(5, true, true, false), Some(0..0x8000));
+    }
+
+    #[test]
+    fn complement() {
+        assert_eq!(range(1, false, false, true), Some(0..CAPACITY - 0x4_0000));
+        assert_eq!(range(1, true, true, true), Some(0x1000..CAPACITY));
+        assert_eq!(range(0, false, false, true), Some(0..CAPACITY));
+        assert_eq!(range(7, false, false, true), None);
+    }
+
+    #[test]
+    fn blocks_of_large_parts() {
+        const BIG: u32 = 32 * 1024 * 1024;
+        let range = |bp, top_bottom, complement| {
+            BlockProtect {
+                layout: Layout::Blocks,
+                bp,
+                top_bottom,
+                sector: false,
+                complement,
+            }
+            .range(BIG)
+        };
+        assert_eq!(range(1, false, false), Some(BIG - 0x1_0000..BIG));
+        assert_eq!(range(1, true, false), Some(0..0x1_0000));
+        assert_eq!(range(9, false, false), Some(BIG / 2..BIG));
+        assert_eq!(range(10, false, false), Some(0..BIG));
+        assert_eq!(range(15, true, false), Some(0..BIG));
+        assert_eq!(range(1, false, true), Some(0..BIG - 0x1_0000));
+    }
+}
@@ -0,0 +1,240 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::{BusWidth, QuadCommand, QuadSerialInterface};
+use crate::{Error, FlashOperations, QuadEnable, define};
+
+/// Bus mode used by `quad_read_data` and `quad_write_data`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuadMode {
+    /// Plain 1-1-1 SPI
+    Single,
+    /// 1-1-4, data phase on four lines
+    QuadOutput,
+    /// 1-4-4, address and data phases on four lines
+    QuadIo,
+    /// 4-4-4, every phase of every command on four lines
+    Qpi,
+}
+
+impl<I> Flash<I>
+where
+    I: QuadSerialInterface,
+{
+    pub fn quad_mode(&self) -> QuadMode {
+        self.quad_mode
+    }
+
+    /// Set the QE bit as needed and switch the chip and interface into `mode`
+    pub fn set_quad_mode(&mut self, mode: QuadMode) -> Result<(), Error<I::Error>> {
+        if mode == self.quad_mode {
+            return Ok(());
+        }
+        self.wake_for_access()?;
+
+        let supported = match mode {
+            QuadMode::Single => true,
+            QuadMode::QuadOutput => self.flash_info.quad_output_read.is_some(),
+            QuadMode::QuadIo => self.flash_info.quad_io_read.is_some(),
+            QuadMode::Qpi => {
+                self.flash_info.qpi_read.is_some()
+                    && self.flash_info.qpi_enter.is_some()
+                    && self.flash_info.qpi_exit.is_some()
+            }
+        };
+        if !supported {
+            error!("Quad mode {:?} is not supported by this flash", mode);
+            return Err(Error::Unsupported);
+        }
+
+        if self.quad_mode == QuadMode::Qpi {
+            self.exit_qpi()?;
+        }
+        self.set_quad_enable(mode != QuadMode::Single)?;
+        if mode == QuadMode::Qpi {
+            self.enter_qpi()?;
+        }
+
+        info!("Quad mode: {:?}", mode);
+        self.quad_mode = mode;
+        Ok(())
+    }
+
+    /// `reset` for a chip that may have been left in QPI mode
+    ///
+    /// Leaves continuous read and QPI mode on four lines before the regular reset sequence.
+    pub fn quad_reset(&mut self) -> Result<(), Error<I::Error>> {
+        self.interface.set_command_width(BusWidth::Quad);
+        let mut ret = super::reset::exit_continuous_read(&mut self.interface);
+        if let Some(opcode) = self.flash_info.qpi_exit {
+            ret = ret.and_then(|_| self.interface.write(&[opcode], None));
+        }
+        self.interface.set_command_width(BusWidth::Single);
+        ret.map_err(|e| {
+            error!("Failed to exit QPI mode");
+            Error::Interface(e)
+        })?;
+
+        self.quad_mode = QuadMode::Single;
+        self.xip = false;
+        self.reset()
+    }
+
+    fn set_quad_enable(&mut self, enable: bool) -> Result<(), Error<I::Error>> {
+        let quad_enable = self.flash_info.quad_enable;
+        let (read_opcode, bit) = match quad_enable {
+            QuadEnable::None => return Ok(()),
+            QuadEnable::Sr1Bit6 => (define::ReadCmd::Status1 as u8, 1 << 6),
+            QuadEnable::Sr2Bit7 => (define::QuadCmd::ReadStatus2Alt as u8, 1 << 7),
+            QuadEnable::Sr2Bit1 | QuadEnable::Sr2Bit1WriteSr2 => {
+                (define::ReadCmd::Status2 as u8, 1 << 1)
+            }
+        };
+
+        let value = self.read_register(read_opcode)?;
+        let new_value = if enable { value | bit } else { value & !bit };
+        if new_value == value {
+            return Ok(());
+        }
+
+        let (cmd, cmd_len) = match quad_enable {
+            QuadEnable::Sr2Bit1 => {
+                let sr1 = self.read_status()?;
+                ([define::WriteCmd::WrietStatus as u8, sr1, new_value], 3)
+            }
+            QuadEnable::Sr2Bit1WriteSr2 => {
+                ([define::WriteCmd::WriteStatus2 as u8, new_value, 0], 2)
+            }
+            QuadEnable::Sr2Bit7 => ([define::QuadCmd::WriteStatus2Alt as u8, new_value, 0], 2),
+            _ => ([define::WriteCmd::WrietStatus as u8, new_value, 0], 2),
+        };
+        self.write_operation(|s| {
+            s.interface
+                .write(&cmd[..cmd_len], None)
+                .map_err(Error::Interface)?;
+            s.wait_busy(s.flash_info.timeouts.write_status)?;
+            Ok(())
+        })?;
+
+        if (self.read_register(read_opcode)? & bit != 0) != enable {
+            error!("Failed to set QE bit to {}", enable);
+            return Err(Error::QuadEnable);
+        }
+        Ok(())
+    }
+
+    fn enter_qpi(&mut self) -> Result<(), Error<I::Error>> {
+        let Some(opcode) = self.flash_info.qpi_enter else {
+            return Err(Error::Unsupported);
+        };
+        self.interface
+            .write(&[opcode], None)
+            .map_err(Error::Interface)?;
+        self.interface.set_command_width(BusWidth::Quad);
+        Ok(())
+    }
+
+    fn exit_qpi(&mut self) -> Result<(), Error<I::Error>> {
+        let Some(opcode) = self.flash_info.qpi_exit else {
+            return Err(Error::Unsupported);
+        };
+        self.interface
+            .write(&[opcode], None)
+            .map_err(Error::Interface)?;
+        self.interface.set_command_width(BusWidth::Single);
+        Ok(())
+    }
+
+    /// Read with the fast read instruction of the current quad mode
+    ///
+    /// In QPI mode this must be used instead of `read_data`, which is single-line only.
+    pub fn quad_read_data(
+        &mut self,
+        address: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let (read, opcode_width, address_width) = match self.quad_mode {
+            QuadMode::Single => return self.read_data(address, buffer),
+            QuadMode::QuadOutput => (
+                self.flash_info.quad_output_read,
+                BusWidth::Single,
+                BusWidth::Single,
+            ),
+            QuadMode::QuadIo => (
+                self.flash_info.quad_io_read,
+                BusWidth::Single,
+                BusWidth::Quad,
+            ),
+            QuadMode::Qpi => (self.flash_info.qpi_read, BusWidth::Quad, BusWidth::Quad),
+        };
+        let Some(read) = read else {
+            return Err(Error::Unsupported);
+        };
+        self.wake_for_access()?;
+
+        if !self.flash_info.contains(address, buffer.len()) {
+            error!(
+                "Read out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                buffer.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+
+        let mut offset = 0_usize;
+        while offset < buffer.len() {
+            let addr = address + offset as u32;
+            let len = self.segment_len(addr, buffer.len() - offset);
+            let cmd = self.read_command(read, addr, opcode_width, address_width, BusWidth::Quad)?;
+            self.interface
+                .quad_read(&cmd, &mut buffer[offset..offset + len])
+                .map_err(|e| {
+                    error!("Failed to read data from address {:08X}", addr);
+                    Error::Interface(e)
+                })?;
+            offset += len;
+        }
+        self.restore_segment()
+    }
+
+    /// Program with the quad page program instruction (0x32)
+    pub fn quad_write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        match self.quad_mode {
+            // In QPI mode 0x02 already runs on four lines
+            QuadMode::Single | QuadMode::Qpi => self.write_data(address, data),
+            QuadMode::QuadOutput | QuadMode::QuadIo => {
+                self.write_pages(address, data, Self::quad_page_write)
+            }
+        }
+    }
+
+    fn quad_page_write(&mut self, address: u32, data: &[u8]) -> Result<(), Error<I::Error>> {
+        if data.len() > self.flash_info.page_size {
+            error!("Data size exceeds {} bytes", self.flash_info.page_size);
+            return Err(Error::OutOfBounds);
+        }
+
+        let (opcode, address_len) =
+            self.address_opcode(define::QuadCmd::PageProgram as u8, address)?;
+        let cmd = QuadCommand {
+            opcode,
+            opcode_width: BusWidth::Single,
+            address: Some(address),
+            address_len: address_len as u8,
+            address_width: BusWidth::Single,
+            mode_bits: None,
+            dummy_cycles: 0,
+            data_width: BusWidth::Quad,
+        };
+        self.write_operation(|s| {
+            s.interface
+                .quad_write(&cmd, Some(data))
+                .map_err(Error::Interface)?;
+            s.wait_busy(s.flash_info.timeouts.page_program)?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::{BusWidth, QuadCommand, SerialInterface};
+use crate::{Error, ReadCommand, define};
+
+const NORMAL_READ: ReadCommand = ReadCommand {
+    opcode: define::ReadCmd::Data as u8,
+    mode_clocks: 0,
+    dummy_clocks: 0,
+};
+
+/// 0x0B takes one dummy byte on every part
+const FAST_READ: ReadCommand = ReadCommand {
+    opcode: define::ReadCmd::Fast as u8,
+    mode_clocks: 0,
+    dummy_clocks: 8,
+};
+
+/// Instruction used by `read_data`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadMode {
+    /// 0x03, no dummy cycles but limited to a lower clock on most parts
+    Normal,
+    /// 0x0B with 8 dummy clocks, runs at the full clock
+    Fast,
+    /// 1-1-2, data phase on two lines (0x3B)
+    DualOutput,
+    /// 1-2-2, address, mode and data phases on two lines (0xBB)
+    DualIo,
+}
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    pub fn read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    /// Select the read instruction, dual modes need both the flash and interface to support them
+    pub fn set_read_mode(&mut self, mode: ReadMode) -> Result<(), Error<I::Error>> {
+        let supported = match mode {
+            ReadMode::Normal | ReadMode::Fast => true,
+            ReadMode::DualOutput => self.flash_info.dual_output_read.is_some(),
+            ReadMode::DualIo => self.flash_info.dual_io_read.is_some(),
+        };
+        if !supported {
+            error!("Read mode {:?} is not supported by this flash", mode);
+            return Err(Error::Unsupported);
+        }
+        if matches!(mode, ReadMode::DualOutput | ReadMode::DualIo)
+            && !self.interface.supports_width(BusWidth::Dual)
+        {
+            error!("Read mode {:?} is not supported by the interface", mode);
+            return Err(Error::Unsupported);
+        }
+
+        info!("Read mode: {:?}", mode);
+        self.read_mode = mode;
+        Ok(())
+    }
+
+    /// Build a read command, splitting mode clocks into mode bits and extra dummy clocks
+    pub(super) fn read_command(
+        &mut self,
+        read: ReadCommand,
+        address: u32,
+        opcode_width: BusWidth,
+        address_width: BusWidth,
+        data_width: BusWidth,
+    ) -> Result<QuadCommand, Error<I::Error>> {
+        let mode_clocks = match address_width {
+            BusWidth::Single => 8,
+            BusWidth::Dual => 4,
+            BusWidth::Quad => 2,
+        };
+        let (mode_bits, dummy_cycles) = if read.mode_clocks >= mode_clocks {
+            (
+                Some(0xFF),
+                read.dummy_clocks + read.mode_clocks - mode_clocks,
+            )
+        } else {
+            (None, read.dummy_clocks + read.mode_clocks)
+        };
+        let (opcode, address_len) = self.address_opcode(read.opcode, address)?;
+        Ok(QuadCommand {
+            opcode,
+            opcode_width,
+            address: Some(address),
+            address_len: address_len as u8,
+            address_width,
+            mode_bits,
+            dummy_cycles,
+            data_width,
+        })
+    }
+
+    /// Instruction of the current read mode with its address and data widths
+    pub(super) fn mode_read(&self) -> Result<(ReadCommand, BusWidth, BusWidth), Error<I::Error>> {
+        let (read, address_width, data_width) = match self.read_mode {
+            ReadMode::Normal => (Some(NORMAL_READ), BusWidth::Single, BusWidth::Single),
+            ReadMode::Fast => (Some(FAST_READ), BusWidth::Single, BusWidth::Single),
+            ReadMode::DualOutput => (
+                self.flash_info.dual_output_read,
+                BusWidth::Single,
+                BusWidth::Dual,
+            ),
+            ReadMode::DualIo => (self.flash_info.dual_io_read, BusWidth::Dual, BusWidth::Dual),
+        };
+        match read {
+            Some(read) => Ok((read, address_width, data_width)),
+            None => Err(Error::Unsupported),
+        }
+    }
+
+    /// One read within a segment, with the instruction of the current read mode
+    pub(super) fn read_segment(
+        &mut self,
+        address: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        if self.read_mode == ReadMode::Normal {
+            let mut cmd = [0_u8; 5];
+            let cmd_len = self.address_command(define::ReadCmd::Data as u8, address, &mut cmd)?;
+            return self
+                .interface
+                .write_and_read(&cmd[..cmd_len], buffer)
+                .map_err(Error::Interface);
+        }
+        let (read, address_width, data_width) = self.mode_read()?;
+        let cmd = self.read_command(read, address, BusWidth::Single, address_width, data_width)?;
+        self.interface
+            .command_read(&cmd, buffer)
+            .map_err(Error::Interface)
+    }
+}
@@ -0,0 +1,92 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, FlashOperations, SoftReset, define};
+
+/// tRST, time the chip needs to come out of a soft reset when it was idle
+const RESET_DELAY_US: u32 = 50;
+
+/// End continuous read mode, 16 clocks of all ones also cover 4-byte addresses
+pub(super) fn exit_continuous_read<I: SerialInterface>(interface: &mut I) -> Result<(), I::Error> {
+    let cmd = [define::ModeCmd::ModeReset as u8; 2];
+    interface.write(&cmd, None)
+}
+
+pub(super) fn soft_reset<I: SerialInterface>(
+    interface: &mut I,
+    method: SoftReset,
+) -> Result<(), I::Error> {
+    match method {
+        SoftReset::None => return Ok(()),
+        SoftReset::EnableReset => {
+            interface.write(&[define::ModeCmd::ResetEnable as u8], None)?;
+            interface.write(&[define::ModeCmd::Reset as u8], None)?;
+        }
+        SoftReset::ResetF0 => interface.write(&[define::ModeCmd::ResetF0 as u8], None)?,
+    }
+    interface.delay_us(RESET_DELAY_US);
+    Ok(())
+}
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    /// Bring the chip back to its power-on state, then reapply the address mode and
+    /// the protection handling of `InitOptions`
+    ///
+    /// Commands go out on the current command width, use `quad_reset` for a chip in QPI mode.
+    pub fn reset(&mut self) -> Result<(), Error<I::Error>> {
+        self.reset_chip()?;
+        self.apply_setup()
+    }
+
+    /// Address mode and protection as set up by `with_options`, which a reset loses
+    pub(super) fn apply_setup(&mut self) -> Result<(), Error<I::Error>> {
+        self.set_4byte_address_mode()?;
+        if !self.keep_protection {
+            self.write_state(true, 0x00)?;
+        }
+        self.refresh_protection()
+    }
+
+    pub(super) fn reset_chip(&mut self) -> Result<(), Error<I::Error>> {
+        #[cfg(feature = "qspi")]
+        if self.quad_mode == super::QuadMode::Qpi {
+            error!("Reset from QPI mode needs quad_reset");
+            return Err(Error::Unsupported);
+        }
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            error!("Reset is only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+
+        // A chip in deep power-down ignores the reset
+        self.powered_down = self.flash_info.power_down.is_some();
+        self.wake_for_access()?;
+
+        let reset = self.flash_info.reset;
+        exit_continuous_read(&mut self.interface).map_err(Error::Interface)?;
+        if reset.soft_reset == SoftReset::None && reset.exit_4byte {
+            if reset.exit_4byte_wren {
+                self.write_enable(true)?;
+            }
+            self.interface
+                .write(&[define::ModeCmd::Exit4Byte as u8], None)
+                .map_err(Error::Interface)?;
+        }
+        soft_reset(&mut self.interface, reset.soft_reset).map_err(|e| {
+            error!("Failed to reset");
+            Error::Interface(e)
+        })?;
+        // An interrupted program or erase makes the reset take longer
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+
+        self.erasing = None;
+        self.resumed = false;
+        info!("Reset");
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, SecurityInfo, define};
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    fn security_info(&self) -> Result<SecurityInfo, Error<I::Error>> {
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            error!("Security registers are only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+        self.flash_info.security.ok_or_else(|| {
+            error!("Security registers are not supported by this flash");
+            Error::Unsupported
+        })
+    }
+
+    /// Factory programmed unique ID, 64-bit IDs occupy the low half
+    pub fn read_unique_id(&mut self) -> Result<u128, Error<I::Error>> {
+        let info = self.security_info()?;
+        self.wake_for_access()?;
+
+        // Dummy bytes take the place of the address plus one
+        let mut cmd = [0_u8; 6];
+        cmd[0] = define::IdCmd::ReadUnique as u8;
+        let cmd_len = self.address_len() + 2;
+        let mut buff = [0_u8; 16];
+        let id = &mut buff[..info.unique_id_len.min(16)];
+        self.interface
+            .write_and_read(&cmd[..cmd_len], id)
+            .map_err(|e| {
+                error!("Failed to read unique ID");
+                Error::Interface(e)
+            })?;
+        Ok(id.iter().fold(0_u128, |acc, &b| (acc << 8) | b as u128))
+    }
+
+    /// Access to the one-time programmable security registers
+    pub fn security_registers(&mut self) -> Result<SecurityRegisters<'_, I>, Error<I::Error>> {
+        let info = self.security_info()?;
+        Ok(SecurityRegisters { flash: self, info })
+    }
+}
+
+/// One-time programmable security registers, borrowed from a `Flash`
+///
+/// Registers are numbered from 0. Once locked, a register can never be
+/// programmed or erased again.
+pub struct SecurityRegisters<'a, I>
+where
+    I: SerialInterface,
+{
+    flash: &'a mut Flash<I>,
+    info: SecurityInfo,
+}
+
+impl<I> SecurityRegisters<'_, I>
+where
+    I: SerialInterface,
+{
+    pub fn count(&self) -> u8 {
+        self.info.count
+    }
+
+    /// Bytes per register
+    pub fn size(&self) -> usize {
+        self.info.size
+    }
+
+    fn address(&self, index: u8, offset: usize, len: usize) -> Result<u32, Error<I::Error>> {
+        if index >= self.info.count || offset + len > self.info.size {
+            error!(
+                "Security register {} + {} + {} out of bounds",
+                index, offset, len
+            );
+            return Err(Error::OutOfBounds);
+        }
+        Ok(((index as u32 + 1) << 12) + offset as u32)
+    }
+
+    fn check_unlocked(&mut self, index: u8) -> Result<(), Error<I::Error>> {
+        if self.is_locked(index)? {
+            error!("Security register {} is locked", index);
+            return Err(Error::WriteProtected);
+        }
+        Ok(())
+    }
+
+    pub fn read(
+        &mut self,
+        index: u8,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = self.address(index, offset, buffer.len())?;
+        let flash = &mut *self.flash;
+        flash.wake_for_access()?;
+
+        // One dummy byte after the address
+        let mut cmd = [define::SecurityCmd::Read as u8, 0, 0, 0, 0, 0];
+        flash.make_address_byte_array(address, &mut cmd[1..]);
+        let cmd_len = flash.address_len() + 2;
+        flash
+            .interface
+            .write_and_read(&cmd[..cmd_len], buffer)
+            .map_err(|e| {
+                error!("Failed to read security register {}", index);
+                Error::Interface(e)
+            })
+    }
+
+    /// Program `data` at `offset`, bits can only go from 1 to 0 until the register is erased
+    pub fn program(
+        &mut self,
+        index: u8,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = self.address(index, offset, data.len())?;
+        self.flash.wake_for_access()?;
+        self.check_unlocked(index)?;
+
+        let page_size = self.flash.flash_info.page_size;
+        let mut done = 0_usize;
+        while done < data.len() {
+            let addr = address + done as u32;
+            let len = (page_size - addr as usize % page_size).min(data.len() - done);
+            let chunk = &data[done..done + len];
+            self.flash.write_operation(|s| {
+                let mut cmd = [define::SecurityCmd::Program as u8, 0, 0, 0, 0];
+                s.make_address_byte_array(addr, &mut cmd[1..]);
+                let cmd_len = s.address_len() + 1;
+                s.interface
+                    .write(&cmd[..cmd_len], Some(chunk))
+                    .map_err(|e| {
+                        error!("Failed to program security register {}", index);
+                        Error::Interface(e)
+                    })?;
+                s.wait_busy(s.flash_info.timeouts.page_program)?;
+                Ok(())
+            })?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    pub fn erase(&mut self, index: u8) -> Result<(), Error<I::Error>> {
+        let address = self.address(index, 0, 0)?;
+        self.flash.wake_for_access()?;
+        self.check_unlocked(index)?;
+
+        self.flash.write_operation(|s| {
+            let mut cmd = [define::SecurityCmd::Erase as u8, 0, 0, 0, 0];
+            s.make_address_byte_array(address, &mut cmd[1..]);
+            let cmd_len = s.address_len() + 1;
+            s.interface.write(&cmd[..cmd_len], None).map_err(|e| {
+                error!("Failed to erase security register {}", index);
+                Error::Interface(e)
+            })?;
+            s.wait_busy(s.flash_info.timeouts.sector_erase)?;
+            Ok(())
+        })
+    }
+
+    pub fn is_locked(&mut self, index: u8) -> Result<bool, Error<I::Error>> {
+        self.address(index, 0, 0)?;
+        let sr = self.flash.read_status_registers()?;
+        Ok(sr.lock_bits() & (1 << index) != 0)
+    }
+
+    /// Permanently lock register `index` by setting its LB bit
+    ///
+    /// This cannot be undone, the register stays read-only for the life of the chip.
+    pub fn lock(&mut self, index: u8) -> Result<(), Error<I::Error>> {
+        self.address(index, 0, 0)?;
+        let mut sr = self.flash.read_status_registers()?;
+        sr.set_lock_bits(sr.lock_bits() | (1 << index));
+        self.flash.write_status_registers(&sr, false)?;
+
+        if !self.is_locked(index)? {
+            error!("Status register is locked, LB{} unchanged", index + 1);
+            return Err(Error::WriteProtected);
+        }
+        info!("Security register {} locked", index);
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, FlashOperations, SuspendInfo, define};
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    fn suspend_info(&self) -> Result<SuspendInfo, Error<I::Error>> {
+        self.check_spi_mode()?;
+        self.flash_info.suspend.ok_or_else(|| {
+            error!("Suspend is not supported by this flash");
+            Error::Unsupported
+        })
+    }
+
+    pub fn is_busy(&mut self) -> Result<bool, Error<I::Error>> {
+        Ok(self.read_status()? & define::STATUS::BUSY as u8 != 0)
+    }
+
+    pub fn is_suspended(&mut self) -> Result<bool, Error<I::Error>> {
+        let status = self.suspend_info()?.status;
+        let mut buff = [0_u8; 1];
+        self.interface
+            .write_and_read(&[status.opcode], &mut buff)
+            .map_err(|e| {
+                error!("Failed to read suspend status");
+                Error::Interface(e)
+            })?;
+        Ok(buff[0] & status.mask != 0)
+    }
+
+    /// Start erasing without waiting for it to finish
+    ///
+    /// `size` must be one of the erase type sizes, or the whole chip. Poll `is_busy`
+    /// until the erase is done; other writes time out in the meantime.
+    pub fn start_erase(&mut self, address: u32, size: usize) -> Result<(), Error<I::Error>> {
+        self.check_spi_mode()?;
+        self.wake_for_access()?;
+        self.check_erase(address, size)?;
+
+        let opcode = if address == 0 && size == self.flash_info.capacity {
+            define::EraseCmd::Chip as u8
+        } else {
+            let erase_type = self
+                .flash_info
+                .erase_types
+                .iter()
+                .flatten()
+                .find(|t| t.size as usize == size && address.is_multiple_of(t.size));
+            let Some(erase_type) = erase_type else {
+                error!("No erase type matches {:08X} + {}", address, size);
+                return Err(Error::NotAligned);
+            };
+            erase_type.opcode
+        };
+
+        let mut cmd = [opcode, 0, 0, 0, 0];
+        let cmd_len = if opcode == define::EraseCmd::Chip as u8 {
+            1
+        } else {
+            // The segment stays selected until the erase is done
+            self.address_command(opcode, address, &mut cmd)?
+        };
+
+        // WEL clears by itself once the erase is done, so no write disable here
+        self.write_enable(true)?;
+        self.interface.write(&cmd[..cmd_len], None).map_err(|e| {
+            error!("Failed to erase block at address {:08X}", address);
+            Error::Interface(e)
+        })?;
+        self.erasing = Some((address, size));
+        Ok(())
+    }
+
+    /// Suspend the program or erase in progress
+    ///
+    /// Returns `false` if the chip was idle or finished before the suspend took effect.
+    pub fn suspend(&mut self) -> Result<bool, Error<I::Error>> {
+        let info = self.suspend_info()?;
+        if !self.is_busy()? {
+            return Ok(false);
+        }
+
+        // A suspend too soon after a resume stops the operation from ever finishing
+        if self.resumed {
+            self.interface.delay_us(info.resume_interval);
+            self.resumed = false;
+        }
+
+        self.interface.write(&[info.suspend], None).map_err(|e| {
+            error!("Failed to suspend");
+            Error::Interface(e)
+        })?;
+        self.wait_busy(info.suspend_latency)?;
+
+        let suspended = self.is_suspended()?;
+        if suspended {
+            info!("Suspended");
+        }
+        Ok(suspended)
+    }
+
+    /// Resume a suspended program or erase, does nothing if none is suspended
+    pub fn resume(&mut self) -> Result<(), Error<I::Error>> {
+        let info = self.suspend_info()?;
+        if !self.is_suspended()? {
+            return Ok(());
+        }
+
+        self.interface.write(&[info.resume], None).map_err(|e| {
+            error!("Failed to resume");
+            Error::Interface(e)
+        })?;
+        self.resumed = true;
+        info!("Resumed");
+        Ok(())
+    }
+
+    /// Let `read_data` suspend an erase started with `start_erase` and resume it afterwards
+    pub fn set_suspend_on_read(&mut self, enable: bool) -> Result<(), Error<I::Error>> {
+        if enable {
+            self.suspend_info()?;
+        }
+        self.suspend_on_read = enable;
+        Ok(())
+    }
+
+    /// Suspend an erase in progress so `read_data` can run, returns `true` if it did
+    pub(super) fn suspend_for_read(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<bool, Error<I::Error>> {
+        if !self.suspend_on_read {
+            return Ok(false);
+        }
+        let Some((start, size)) = self.erasing else {
+            return Ok(false);
+        };
+        // Idle, or already suspended by the caller
+        if !self.is_busy()? {
+            return Ok(false);
+        }
+
+        // The region being erased only reads back valid data once the erase is done
+        if (address as usize) < start as usize + size && (start as usize) < address as usize + len {
+            let timeout = if size == self.flash_info.capacity {
+                self.flash_info.timeouts.chip_erase
+            } else {
+                self.flash_info.erase_timeout(size as u32)
+            };
+            self.wait_busy(timeout)?;
+            self.erasing = None;
+            return Ok(false);
+        }
+
+        self.suspend()
+    }
+}
@@ -0,0 +1,112 @@
+use log::error;
+
+use super::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, FlashOperations};
+
+/// Bytes read back per step while verifying
+const VERIFY_CHUNK: usize = 64;
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    /// Replace the bytes at `address` with `data`, keeping the rest of the touched sectors
+    ///
+    /// `scratch` holds one sector at a time and must be at least the sector size. Sectors
+    /// already holding `data` are left alone, and a sector is only erased when some bit
+    /// has to go from 0 to 1. Every rewritten sector is read back and compared.
+    pub fn update(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        scratch: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let sector_size = self.flash_info.secter_size as usize;
+        let Some(scratch) = scratch.get_mut(..sector_size) else {
+            error!(
+                "Scratch buffer of {} bytes is smaller than a sector",
+                scratch.len()
+            );
+            return Err(Error::BufferTooSmall);
+        };
+        if !self.flash_info.contains(address, data.len()) {
+            error!(
+                "Update out of bounds: address {:08X} + size {} > flash size {}",
+                address,
+                data.len(),
+                self.flash_info.capacity
+            );
+            return Err(Error::OutOfBounds);
+        }
+
+        let mut done = 0_usize;
+        while done < data.len() {
+            let addr = address as usize + done;
+            let sector = (addr - addr % sector_size) as u32;
+            let offset = addr % sector_size;
+            let len = (sector_size - offset).min(data.len() - done);
+            self.update_sector(sector, offset, &data[done..done + len], scratch)?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn update_sector(
+        &mut self,
+        sector: u32,
+        offset: usize,
+        data: &[u8],
+        scratch: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let current = &mut scratch[offset..offset + data.len()];
+        self.read_data(sector + offset as u32, current)?;
+        let Some(first) = current.iter().zip(data).position(|(old, new)| old != new) else {
+            return Ok(());
+        };
+        let last = current
+            .iter()
+            .zip(data)
+            .rposition(|(old, new)| old != new)
+            .unwrap_or(first);
+
+        // Programming can only clear bits
+        if current.iter().zip(data).all(|(old, new)| old & new == *new) {
+            let start = sector + (offset + first) as u32;
+            self.write_data(start, &data[first..=last])?;
+            return self.verify(start, &data[first..=last]);
+        }
+
+        // The rest of the sector is kept around the erase
+        self.read_data(sector, &mut scratch[..offset])?;
+        let end = offset + data.len();
+        self.read_data(sector + end as u32, &mut scratch[end..])?;
+        scratch[offset..end].copy_from_slice(data);
+
+        self.erase(sector, scratch.len())?;
+        let page_size = self.flash_info.page_size;
+        for (i, page) in scratch.chunks(page_size).enumerate() {
+            // Erased pages already read back as 0xFF
+            if page.iter().any(|&b| b != 0xFF) {
+                self.write_data(sector + (i * page_size) as u32, page)?;
+            }
+        }
+        self.verify(sector, scratch)
+    }
+
+    /// Read back `expected` at `address` in small steps
+    fn verify(&mut self, address: u32, expected: &[u8]) -> Result<(), Error<I::Error>> {
+        let mut buff = [0_u8; VERIFY_CHUNK];
+        for (i, chunk) in expected.chunks(VERIFY_CHUNK).enumerate() {
+            let addr = address + (i * VERIFY_CHUNK) as u32;
+            let read = &mut buff[..chunk.len()];
+            self.read_data(addr, read)?;
+            if let Some(pos) = read.iter().zip(chunk).position(|(a, b)| a != b) {
+                let address = addr + pos as u32;
+                error!("Verify failed at address {:08X}", address);
+                return Err(Error::VerifyFailed { address });
+            }
+        }
+        Ok(())
+    }
+}
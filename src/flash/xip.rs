@@ -0,0 +1,103 @@
+use log::{error, info};
+
+use super::Flash;
+use crate::serial_interface::{BusWidth, QuadCommand, SerialInterface};
+use crate::{Error, ReadCommand};
+
+/// M7-M0 that keep the chip in continuous read mode
+///
+/// M5-M4 = 10 is what Winbond, GigaDevice and ISSI look for, Macronix wants the
+/// two nibbles to differ. Anything else, like 0xFF, ends the mode after the read.
+const XIP_MODE_BITS: u8 = 0xA5;
+
+impl<I> Flash<I>
+where
+    I: SerialInterface,
+{
+    pub fn is_xip(&self) -> bool {
+        self.xip
+    }
+
+    /// I/O read used for XIP, from the current quad or read mode
+    fn xip_read(&self) -> Option<(ReadCommand, BusWidth)> {
+        #[cfg(feature = "qspi")]
+        if self.quad_mode == super::QuadMode::QuadIo {
+            return self
+                .flash_info
+                .quad_io_read
+                .map(|read| (read, BusWidth::Quad));
+        }
+        if self.read_mode == super::ReadMode::DualIo {
+            return self
+                .flash_info
+                .dual_io_read
+                .map(|read| (read, BusWidth::Dual));
+        }
+        None
+    }
+
+    /// Read command the chip is left expecting by `enter_xip`, the opcode is only sent once
+    pub fn xip_command(&mut self) -> Result<QuadCommand, Error<I::Error>> {
+        let Some((read, width)) = self.xip_read() else {
+            error!("XIP needs the dual I/O read mode or the quad I/O mode");
+            return Err(Error::Unsupported);
+        };
+        if !self.interface.supports_width(width) {
+            error!("XIP on {:?} lines is not supported by the interface", width);
+            return Err(Error::Unsupported);
+        }
+        let mut cmd = self.read_command(read, 0, BusWidth::Single, width, width)?;
+        if cmd.mode_bits.is_none() {
+            error!("Read {:02X} has no mode bits for XIP", read.opcode);
+            return Err(Error::Unsupported);
+        }
+        cmd.mode_bits = Some(XIP_MODE_BITS);
+        Ok(cmd)
+    }
+
+    /// Put the chip in continuous read mode for a memory-mapped controller
+    ///
+    /// Uses 0xEB in `QuadMode::QuadIo`, otherwise 0xBB in `ReadMode::DualIo`. Until
+    /// `exit_xip`, the chip takes every transfer as the address of another read, so
+    /// any other driver operation leaves XIP first.
+    pub fn enter_xip(&mut self) -> Result<(), Error<I::Error>> {
+        if self.xip {
+            return Ok(());
+        }
+        #[cfg(feature = "ospi")]
+        if self.octal_mode != super::OctalMode::Spi {
+            error!("XIP is only supported in SPI mode");
+            return Err(Error::Unsupported);
+        }
+        self.wake_for_access()?;
+        let cmd = self.xip_command()?;
+        self.wait_busy(self.flash_info.timeouts.write_status)?;
+
+        // The mode bits of this read arm the mode, the data itself is not needed
+        let mut byte = [0_u8; 1];
+        self.interface.command_read(&cmd, &mut byte).map_err(|e| {
+            error!("Failed to enter XIP");
+            Error::Interface(e)
+        })?;
+        self.xip = true;
+        info!("Entered XIP with {:02X}", cmd.opcode);
+        Ok(())
+    }
+
+    /// Leave continuous read mode so the chip decodes opcodes again
+    ///
+    /// A memory-mapped controller is switched back to indirect mode first.
+    pub fn exit_xip(&mut self) -> Result<(), Error<I::Error>> {
+        self.leave_memory_mapped()?;
+        if !self.xip {
+            return Ok(());
+        }
+        super::reset::exit_continuous_read(&mut self.interface).map_err(|e| {
+            error!("Failed to exit XIP");
+            Error::Interface(e)
+        })?;
+        self.xip = false;
+        info!("Exited XIP");
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::define;
+use crate::serial_interface::SerialInterface;
+
+/// JEP106 continuation code, repeated once per bank above the first
+const CONTINUATION: u8 = 0x7F;
+/// Longest continuation code run accepted before the manufacturer ID
+const MAX_CONTINUATION: usize = 8;
+
+/// Identification of the fitted part
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlashId {
+    /// JEP106 manufacturer ID from 0x9F, without the continuation codes
+    pub manufacturer: u8,
+    pub memory_type: u8,
+    /// Capacity code, usually log2 of the size in bytes
+    pub capacity: u8,
+    /// Number of 0x7F continuation codes before the manufacturer ID, the JEP106 bank minus one
+    pub continuation: u8,
+    /// Legacy device ID from 0xAB
+    pub device_id: u8,
+    /// Manufacturer and device ID pair from 0x90
+    pub manufacturer_device_id: [u8; 2],
+}
+
+impl FlashId {
+    /// JEDEC ID as compared against `FlashInfo`
+    pub fn jedec_id(&self) -> [u8; 3] {
+        [self.manufacturer, self.memory_type, self.capacity]
+    }
+
+    pub fn manufacturer_name(&self) -> Option<&'static str> {
+        manufacturer_name(self.continuation, self.manufacturer)
+    }
+
+    pub(crate) fn read<I: SerialInterface>(interface: &mut I) -> Result<Self, I::Error> {
+        let mut jedec = [0_u8; MAX_CONTINUATION + 3];
+        interface.write_and_read(&[define::IdCmd::JedecId as u8], &mut jedec)?;
+        let continuation = jedec[..MAX_CONTINUATION]
+            .iter()
+            .take_while(|&&b| b == CONTINUATION)
+            .count();
+        let jedec = &jedec[continuation..];
+
+        // Three dummy bytes before the legacy device ID
+        let mut device_id = [0_u8; 1];
+        let cmd = [define::IdCmd::DeviceId as u8, 0, 0, 0];
+        interface.write_and_read(&cmd, &mut device_id)?;
+
+        // Address 0 returns the manufacturer ID first
+        let mut manufacturer_device_id = [0_u8; 2];
+        let cmd = [define::IdCmd::Manufacturer as u8, 0, 0, 0];
+        interface.write_and_read(&cmd, &mut manufacturer_device_id)?;
+
+        Ok(FlashId {
+            manufacturer: jedec[0],
+            memory_type: jedec[1],
+            capacity: jedec[2],
+            continuation: continuation as u8,
+            device_id: device_id[0],
+            manufacturer_device_id,
+        })
+    }
+}
+
+/// Manufacturer name for a JEP106 ID, `None` if unknown
+///
+/// Only bank 0 (no continuation codes) is covered. Some serial NOR vendors ship
+/// bank 0 IDs they were never assigned, those map to the flash vendor.
+pub fn manufacturer_name(continuation: u8, id: u8) -> Option<&'static str> {
+    if continuation != 0 {
+        return None;
+    }
+    let name = match id {
+        0x01 => "AMD/Spansion",
+        0x04 => "Fujitsu",
+        0x0B => "XTX",
+        0x1C => "Eon",
+        0x1F => "Atmel/Adesto",
+        0x20 => "STMicroelectronics/Micron",
+        0x2C => "Micron",
+        0x37 => "AMIC",
+        0x5E => "Zbit",
+        0x62 => "Sanyo/ON Semiconductor",
+        0x68 => "Boya",
+        0x85 => "Puya",
+        0x89 => "Intel",
+        0x97 => "Texas Instruments",
+        0x98 => "Kioxia/Toshiba",
+        0x9D => "ISSI",
+        0xA1 => "Fudan",
+        0xAD => "SK hynix",
+        0xBF => "SST/Microchip",
+        0xC2 => "Macronix",
+        0xC8 => "GigaDevice",
+        0xEF => "Winbond",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -1,11 +1,253 @@
 #![no_std]
 
-use core::fmt::Error;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
 
+#[cfg(feature = "async")]
+pub mod async_flash;
 pub mod define;
+mod erase;
+pub mod error;
 pub mod flash;
+pub mod id;
+pub mod parts;
 pub mod serial_interface;
 pub mod sfdp;
+#[cfg(feature = "std")]
+pub mod sim;
+pub mod status;
+#[cfg(feature = "storage")]
+pub mod storage;
+
+pub use error::Error;
+use log::error;
+use sfdp::SFDPInfo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EraseType {
+    pub size: u32,
+    pub opcode: u8,
+}
+
+/// Fast read instruction with its mode and dummy clocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadCommand {
+    pub opcode: u8,
+    pub mode_clocks: u8,
+    pub dummy_clocks: u8,
+}
+
+/// Location of the Quad Enable bit (BFPT DWORD 15 bits 22:20)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuadEnable {
+    /// No QE bit, quad I/O is always available
+    None,
+    /// Bit 1 of status register 2, written with two bytes through 0x01
+    Sr2Bit1,
+    /// Bit 1 of status register 2, written on its own through 0x31
+    Sr2Bit1WriteSr2,
+    /// Bit 6 of status register 1
+    Sr1Bit6,
+    /// Bit 7 of status register 2, read with 0x3F and written with 0x3E
+    Sr2Bit7,
+}
+
+/// How addresses above 16M are reached (BFPT DWORD 16 bits 31:24)
+///
+/// Only `Enter4Byte` leaves the chip in 4-byte mode between commands, the other
+/// methods keep it readable by a 3-byte boot ROM after an MCU reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressMode {
+    /// 3-byte addresses, enough for 16M and below
+    ThreeByte,
+    /// Dedicated 4-byte opcodes (0x13, 0x12, 0x21, ...), the chip stays in 3-byte mode
+    FourByteOpcodes,
+    /// Extended address register written with 0xC5 selects the 16M segment
+    ExtendedAddress,
+    /// Bank register written with 0x17 selects the 16M segment
+    BankRegister,
+    /// 4-byte mode entered with 0xB7, after a write enable if `write_enable`
+    Enter4Byte { write_enable: bool },
+    /// The chip only takes 4-byte addresses
+    Always4Byte,
+}
+
+/// Second command byte used in octal (8-8-8) mode (BFPT DWORD 18 bits 30:29)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandExtension {
+    /// The opcode is sent twice
+    Repeat,
+    /// The opcode is followed by its inverse
+    Invert,
+}
+
+/// Configuration register used to switch between SPI and octal mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OctalEnable {
+    /// Macronix CR2 written with 0x72
+    Macronix,
+    /// Micron/ISSI volatile configuration register written with 0x81
+    Micron,
+}
+
+/// Octal mode parameters, mostly from the SFDP xSPI profile 1.0 table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OctalInfo {
+    pub enable: OctalEnable,
+    pub extension: CommandExtension,
+    pub read_opcode: u8,
+    pub dummy_cycles: u8,
+    pub status_dummy_cycles: u8,
+    /// Read Status needs a 4-byte address in octal mode
+    pub status_address: bool,
+}
+
+/// Register and bits that report a suspended program or erase
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuspendStatus {
+    /// Read register opcode
+    pub opcode: u8,
+    pub mask: u8,
+}
+
+/// 0x50 is only decoded by Winbond and GigaDevice, the others need 0x06
+///
+/// Macronix has no volatile status bits at all, so the write lands in the
+/// non-volatile register.
+fn volatile_write_enable_for(manufacturer_id: u8) -> u8 {
+    match manufacturer_id {
+        0xEF | 0xC8 => define::WriteCmd::VolatileWriteEnable as u8,
+        _ => define::WriteCmd::WriteEnable as u8,
+    }
+}
+
+impl SuspendStatus {
+    /// SUS bits by manufacturer, SR2 bit 7 for Winbond compatible parts
+    pub(crate) fn for_manufacturer(manufacturer_id: u8) -> Self {
+        match manufacturer_id {
+            // Macronix security register: PSB, ESB
+            0xC2 => SuspendStatus {
+                opcode: define::ReadCmd::SecurityStatus as u8,
+                mask: 0b0000_1100,
+            },
+            // Micron flag status register: program and erase suspend
+            0x20 | 0x2C => SuspendStatus {
+                opcode: define::ReadCmd::FlagStatus as u8,
+                mask: 0b0100_0100,
+            },
+            // GigaDevice SR2: SUS1, SUS2
+            0xC8 => SuspendStatus {
+                opcode: define::ReadCmd::Status2 as u8,
+                mask: 0b1000_0100,
+            },
+            _ => SuspendStatus {
+                opcode: define::ReadCmd::Status2 as u8,
+                mask: 0b1000_0000,
+            },
+        }
+    }
+}
+
+/// Erase/program suspend parameters (BFPT DWORD 12 and 13)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuspendInfo {
+    pub suspend: u8,
+    pub resume: u8,
+    /// Longest time, in microseconds, until a suspend takes effect
+    pub suspend_latency: u32,
+    /// Shortest time, in microseconds, from a resume to the next suspend
+    pub resume_interval: u32,
+    pub status: SuspendStatus,
+}
+
+/// Deep power-down opcodes and delays (BFPT DWORD 14)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerDownInfo {
+    pub enter: u8,
+    pub exit: u8,
+    /// tDP, microseconds until the chip is powered down
+    pub enter_delay: u32,
+    /// tRES1, microseconds until the chip accepts commands again
+    pub exit_delay: u32,
+}
+
+/// Software reset instruction (BFPT DWORD 16 bits 12:11)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoftReset {
+    None,
+    /// Reset enable 0x66 followed by reset 0x99
+    EnableReset,
+    /// Single 0xF0 reset
+    ResetF0,
+}
+
+/// How to bring the chip back to its power-on state (BFPT DWORD 16)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResetInfo {
+    pub soft_reset: SoftReset,
+    /// Exit 4-byte address mode with 0xE9 when there is no soft reset
+    pub exit_4byte: bool,
+    /// 0xE9 needs a write enable first
+    pub exit_4byte_wren: bool,
+}
+
+/// One-time programmable security registers and unique ID (0x48/0x42/0x44/0x4B)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecurityInfo {
+    /// Number of registers, register `n` is locked by LB`n + 1`
+    pub count: u8,
+    /// Bytes per register, register `n` starts at address `(n + 1) << 12`
+    pub size: usize,
+    /// Unique ID length in bytes, 8 or 16
+    pub unique_id_len: usize,
+}
+
+impl SecurityInfo {
+    /// Security register layout by manufacturer, not described by SFDP
+    ///
+    /// Other parts, like the Macronix secured OTP area entered with 0xB1, use different
+    /// commands. Compatible ones can opt in with `FlashInfo::with_security`.
+    pub(crate) fn for_manufacturer(manufacturer_id: u8) -> Option<Self> {
+        match manufacturer_id {
+            0xEF => Some(SecurityInfo {
+                count: 3,
+                size: 256,
+                unique_id_len: 8,
+            }),
+            0xC8 => Some(SecurityInfo {
+                count: 3,
+                size: 1024,
+                unique_id_len: 16,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Longest time, in microseconds, the chip may stay busy after each operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    pub page_program: u32,
+    /// Erase of the smallest erase type
+    pub sector_erase: u32,
+    /// Erase of any larger erase type
+    pub block_erase: u32,
+    pub chip_erase: u32,
+    pub write_status: u32,
+}
+
+impl Default for Timeouts {
+    /// Worst case figures of common serial NOR parts
+    fn default() -> Self {
+        Timeouts {
+            page_program: 5_000,
+            sector_erase: 500_000,
+            block_erase: 2_000_000,
+            chip_erase: 400_000_000,
+            write_status: 50_000,
+        }
+    }
+}
 
 pub struct FlashInfo {
     manufacturer_id: u8,
@@ -13,25 +255,297 @@ pub struct FlashInfo {
     capacity_id: u8,
     capacity: usize,
     secter_size: u32,
+    page_size: usize,
+    erase_types: [Option<EraseType>; 4],
+    address_mode: AddressMode,
+    quad_enable: QuadEnable,
+    dual_output_read: Option<ReadCommand>,
+    dual_io_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    quad_output_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    quad_io_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    qpi_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    qpi_enter: Option<u8>,
+    #[cfg(feature = "qspi")]
+    qpi_exit: Option<u8>,
+    #[cfg(feature = "ospi")]
+    octal: Option<OctalInfo>,
+    timeouts: Timeouts,
+    suspend: Option<SuspendInfo>,
+    power_down: Option<PowerDownInfo>,
+    reset: ResetInfo,
+    /// Write enable opcode for volatile status register writes, 0x50 or 0x06
+    volatile_write_enable: u8,
+    security: Option<SecurityInfo>,
 }
 
 impl FlashInfo {
-    pub fn new(manufacturer_id: u8, type_id: u8, capacity_id: u8, capacity: usize, secter_size: u32) -> Self {
+    pub fn new(
+        manufacturer_id: u8,
+        type_id: u8,
+        capacity_id: u8,
+        capacity: usize,
+        secter_size: u32,
+    ) -> Self {
         FlashInfo {
             manufacturer_id,
             type_id,
             capacity_id,
             capacity,
             secter_size,
+            page_size: 256,
+            erase_types: [
+                Some(EraseType {
+                    size: 4 * 1024,
+                    opcode: define::EraseCmd::Sector4k as u8,
+                }),
+                Some(EraseType {
+                    size: 32 * 1024,
+                    opcode: define::EraseCmd::Block32k as u8,
+                }),
+                Some(EraseType {
+                    size: 64 * 1024,
+                    opcode: define::EraseCmd::Block64k as u8,
+                }),
+                None,
+            ],
+            address_mode: if capacity > (1 << 24) {
+                AddressMode::Enter4Byte {
+                    write_enable: false,
+                }
+            } else {
+                AddressMode::ThreeByte
+            },
+            quad_enable: QuadEnable::Sr2Bit1,
+            dual_output_read: Some(ReadCommand {
+                opcode: define::ReadCmd::FastDual as u8,
+                mode_clocks: 0,
+                dummy_clocks: 8,
+            }),
+            dual_io_read: Some(ReadCommand {
+                opcode: define::ReadCmd::FastDualIo as u8,
+                mode_clocks: 4,
+                dummy_clocks: 0,
+            }),
+            #[cfg(feature = "qspi")]
+            quad_output_read: Some(ReadCommand {
+                opcode: define::QuadCmd::FastQuad as u8,
+                mode_clocks: 0,
+                dummy_clocks: 8,
+            }),
+            #[cfg(feature = "qspi")]
+            quad_io_read: Some(ReadCommand {
+                opcode: define::QuadCmd::FastQuadIo as u8,
+                mode_clocks: 2,
+                dummy_clocks: 4,
+            }),
+            #[cfg(feature = "qspi")]
+            qpi_read: None,
+            #[cfg(feature = "qspi")]
+            qpi_enter: None,
+            #[cfg(feature = "qspi")]
+            qpi_exit: None,
+            #[cfg(feature = "ospi")]
+            octal: None,
+            timeouts: Timeouts::default(),
+            suspend: Some(SuspendInfo {
+                suspend: define::EraseCmd::Suspend as u8,
+                resume: define::EraseCmd::Resume as u8,
+                suspend_latency: 50,
+                resume_interval: 100,
+                status: SuspendStatus::for_manufacturer(manufacturer_id),
+            }),
+            power_down: Some(PowerDownInfo {
+                enter: define::ModeCmd::PowerDown as u8,
+                exit: define::ModeCmd::ReleasePowerDown as u8,
+                enter_delay: 3,
+                exit_delay: 30,
+            }),
+            reset: ResetInfo {
+                soft_reset: SoftReset::EnableReset,
+                exit_4byte: true,
+                exit_4byte_wren: false,
+            },
+            volatile_write_enable: volatile_write_enable_for(manufacturer_id),
+            security: SecurityInfo::for_manufacturer(manufacturer_id),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn sector_size(&self) -> u32 {
+        self.secter_size
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    /// Replace the default or SFDP derived timeouts
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn address_mode(&self) -> AddressMode {
+        self.address_mode
+    }
+
+    /// Replace the default or SFDP derived 4-byte addressing method
+    ///
+    /// With `FourByteOpcodes`, erase types without a dedicated 4-byte opcode are dropped.
+    pub fn with_address_mode(mut self, mode: AddressMode) -> Self {
+        self.address_mode = mode;
+        if mode == AddressMode::FourByteOpcodes {
+            for erase_type in self.erase_types.iter_mut() {
+                if erase_type.is_some_and(|t| define::four_byte_opcode(t.opcode).is_none()) {
+                    *erase_type = None;
+                }
+            }
+        }
+        self
+    }
+
+    /// Enable octal mode on a part without an xSPI profile or with an unknown enable sequence
+    #[cfg(feature = "ospi")]
+    pub fn with_octal(mut self, octal: OctalInfo) -> Self {
+        self.octal = Some(octal);
+        self
+    }
+
+    /// Enable the 0x48/0x42/0x44/0x4B security registers on a part not known to have them
+    pub fn with_security(mut self, security: SecurityInfo) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    /// Read opcodes of SR2 and SR3, `None` for registers the part does not have
+    ///
+    /// 0x35 only reads SR2 on parts keeping QE in SR2 bit 1, Macronix, ISSI and Micron
+    /// decode it as enter QPI or quad I/O. SR3 (0x15) is only read with the Winbond layout.
+    pub(crate) fn status_read_opcodes(&self) -> [Option<u8>; 2] {
+        let sr2 = matches!(
+            self.quad_enable,
+            QuadEnable::Sr2Bit1 | QuadEnable::Sr2Bit1WriteSr2
+        )
+        .then_some(define::ReadCmd::Status2 as u8);
+        let sr3 = (sr2.is_some() && self.winbond_status_layout())
+            .then_some(define::ReadCmd::Status3 as u8);
+        [sr2, sr3]
+    }
+
+    /// Winbond and GigaDevice share the SR1 to SR3 layout of `StatusRegisters`
+    ///
+    /// Macronix, ISSI and Micron have BP3 and keep QE or TB at other bits.
+    pub(crate) fn winbond_status_layout(&self) -> bool {
+        matches!(self.manufacturer_id, 0xEF | 0xC8)
+    }
+
+    /// Whether `len` bytes from `address` fit in the chip, without overflowing on 32-bit targets
+    pub(crate) fn contains(&self, address: u32, len: usize) -> bool {
+        (address as usize)
+            .checked_add(len)
+            .is_some_and(|end| end <= self.capacity)
+    }
+
+    /// Check that an erase is aligned to the sector size and to the smallest erase type
+    ///
+    /// Every aligned range can then be split into erase commands without a gap.
+    pub(crate) fn check_erase_alignment<E>(
+        &self,
+        address: u32,
+        size: usize,
+    ) -> Result<(), Error<E>> {
+        let Some(min_size) = self.erase_types.iter().flatten().map(|t| t.size).min() else {
+            error!("No erase type available");
+            return Err(Error::Unsupported);
+        };
+        for alignment in [self.secter_size, min_size] {
+            if !size.is_multiple_of(alignment as usize) || !address.is_multiple_of(alignment) {
+                error!(
+                    "Erase {:08X} + {} is not aligned to {} bytes",
+                    address, size, alignment
+                );
+                return Err(Error::NotAligned);
+            }
         }
+        Ok(())
+    }
+
+    /// Busy timeout for an erase of `size` bytes
+    pub(crate) fn erase_timeout(&self, size: u32) -> u32 {
+        if size <= self.secter_size {
+            self.timeouts.sector_erase
+        } else {
+            self.timeouts.block_erase
+        }
+    }
+
+    pub fn from_sfdp(jedec_id: [u8; 3], sfdp: &SFDPInfo) -> Self {
+        let info = FlashInfo {
+            manufacturer_id: jedec_id[0],
+            type_id: jedec_id[1],
+            capacity_id: jedec_id[2],
+            capacity: sfdp.capacity,
+            secter_size: sfdp.sector_size(),
+            page_size: sfdp.page_size,
+            erase_types: sfdp.erase_types,
+            address_mode: sfdp.address_mode,
+            quad_enable: sfdp.quad_enable,
+            dual_output_read: sfdp.dual_output_read,
+            dual_io_read: sfdp.dual_io_read,
+            #[cfg(feature = "qspi")]
+            quad_output_read: sfdp.quad_output_read,
+            #[cfg(feature = "qspi")]
+            quad_io_read: sfdp.quad_io_read,
+            #[cfg(feature = "qspi")]
+            qpi_read: sfdp.qpi_read,
+            #[cfg(feature = "qspi")]
+            qpi_enter: sfdp.qpi_enter,
+            #[cfg(feature = "qspi")]
+            qpi_exit: sfdp.qpi_exit,
+            #[cfg(feature = "ospi")]
+            octal: sfdp.octal_info(jedec_id[0]),
+            timeouts: sfdp.timeouts,
+            suspend: sfdp.suspend_info(jedec_id[0]),
+            power_down: sfdp.power_down,
+            reset: sfdp.reset,
+            volatile_write_enable: sfdp.volatile_write_enable,
+            security: SecurityInfo::for_manufacturer(jedec_id[0]),
+        };
+        info.with_address_mode(sfdp.address_mode)
     }
 }
 
 pub trait FlashOperations {
-    fn erase_chip(&mut self) -> Result<(), Error>;
-    fn erase(&mut self, address: u32, size: usize) -> Result<(), Error>;
-    fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Error>;
-    fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error>;
-    fn read_status(&mut self) -> Result<u8, Error>;
-    fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Error>;
+    type Error;
+
+    fn erase_chip(&mut self) -> Result<(), Self::Error>;
+    fn erase(&mut self, address: u32, size: usize) -> Result<(), Self::Error>;
+    fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error>;
+    fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    fn read_status(&mut self) -> Result<u8, Self::Error>;
+    fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Self::Error>;
+}
+
+#[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
+pub trait AsyncFlashOperations {
+    type Error;
+
+    async fn erase_chip(&mut self) -> Result<(), Self::Error>;
+    async fn erase(&mut self, address: u32, size: usize) -> Result<(), Self::Error>;
+    async fn write_data(&mut self, address: u32, data: &[u8]) -> Result<(), Self::Error>;
+    async fn read_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    async fn read_status(&mut self) -> Result<u8, Self::Error>;
+    async fn write_state(&mut self, is_volatile: bool, state: u8) -> Result<(), Self::Error>;
 }
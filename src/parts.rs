@@ -0,0 +1,241 @@
+use crate::{AddressMode, FlashInfo, QuadEnable, define};
+
+/// Deviations from the Winbond style defaults of `FlashInfo::new`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// No 32K block erase (0x52)
+    pub no_block_32k: bool,
+    /// Volatile status writes need 0x06, 0x50 is not decoded
+    pub no_volatile_write_enable: bool,
+    /// No erase/program suspend
+    pub no_suspend: bool,
+    /// No deep power-down
+    pub no_power_down: bool,
+}
+
+impl Quirks {
+    const NONE: Quirks = Quirks {
+        no_block_32k: false,
+        no_volatile_write_enable: false,
+        no_suspend: false,
+        no_power_down: false,
+    };
+    /// Volatile status writes go through 0x06
+    const VOLATILE_WREN: Quirks = Quirks {
+        no_volatile_write_enable: true,
+        ..Quirks::NONE
+    };
+    /// No 4-byte opcode for the 32K erase
+    const NO_BLOCK_32K: Quirks = Quirks {
+        no_block_32k: true,
+        ..Quirks::NONE
+    };
+    /// N25Q: no 32K erase, no deep power-down
+    const N25Q: Quirks = Quirks {
+        no_block_32k: true,
+        no_volatile_write_enable: true,
+        no_suspend: false,
+        no_power_down: true,
+    };
+}
+
+/// Known part, 4K sectors are assumed and parts above 16M use dedicated 4-byte opcodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub name: &'static str,
+    pub jedec_id: [u8; 3],
+    pub capacity: usize,
+    pub page_size: usize,
+    pub quad_enable: QuadEnable,
+    pub quirks: Quirks,
+}
+
+impl Part {
+    const fn new(
+        name: &'static str,
+        jedec_id: [u8; 3],
+        capacity: usize,
+        quad_enable: QuadEnable,
+    ) -> Self {
+        Part {
+            name,
+            jedec_id,
+            capacity,
+            page_size: 256,
+            quad_enable,
+            quirks: Quirks::NONE,
+        }
+    }
+
+    const fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn flash_info(&self) -> FlashInfo {
+        let [manufacturer_id, type_id, capacity_id] = self.jedec_id;
+        let mut info = FlashInfo::new(manufacturer_id, type_id, capacity_id, self.capacity, 4096);
+        info.page_size = self.page_size;
+        info.quad_enable = self.quad_enable;
+        if self.quirks.no_block_32k {
+            info.erase_types[1] = None;
+        }
+        if self.quirks.no_volatile_write_enable {
+            info.volatile_write_enable = define::WriteCmd::WriteEnable as u8;
+        }
+        if self.quirks.no_suspend {
+            info.suspend = None;
+        }
+        if self.quirks.no_power_down {
+            info.power_down = None;
+        }
+        if self.capacity > 1 << 24 {
+            info = info.with_address_mode(AddressMode::FourByteOpcodes);
+        }
+        info
+    }
+}
+
+const M: usize = 1024 * 1024;
+
+/// Parts recognised by `Flash::probe`, anything else is read from SFDP
+pub static PARTS: &[Part] = &[
+    // Winbond
+    Part::new("W25Q80", [0xEF, 0x40, 0x14], M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q16", [0xEF, 0x40, 0x15], 2 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q32", [0xEF, 0x40, 0x16], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q64", [0xEF, 0x40, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q128", [0xEF, 0x40, 0x18], 16 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q256", [0xEF, 0x40, 0x19], 32 * M, QuadEnable::Sr2Bit1)
+        .with_quirks(Quirks::NO_BLOCK_32K),
+    Part::new("W25Q512", [0xEF, 0x40, 0x20], 64 * M, QuadEnable::Sr2Bit1)
+        .with_quirks(Quirks::NO_BLOCK_32K),
+    Part::new("W25Q32JW", [0xEF, 0x60, 0x16], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q64JW", [0xEF, 0x60, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new("W25Q128JW", [0xEF, 0x60, 0x18], 16 * M, QuadEnable::Sr2Bit1),
+    Part::new(
+        "W25Q128JV-IM",
+        [0xEF, 0x70, 0x18],
+        16 * M,
+        QuadEnable::Sr2Bit1,
+    ),
+    // GigaDevice
+    Part::new("GD25Q16", [0xC8, 0x40, 0x15], 2 * M, QuadEnable::Sr2Bit1),
+    Part::new("GD25Q32", [0xC8, 0x40, 0x16], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("GD25Q64", [0xC8, 0x40, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new(
+        "GD25Q128",
+        [0xC8, 0x40, 0x18],
+        16 * M,
+        QuadEnable::Sr2Bit1WriteSr2,
+    ),
+    Part::new(
+        "GD25Q256",
+        [0xC8, 0x40, 0x19],
+        32 * M,
+        QuadEnable::Sr2Bit1WriteSr2,
+    ),
+    // Macronix
+    Part::new("MX25L1606E", [0xC2, 0x20, 0x15], 2 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("MX25L3233F", [0xC2, 0x20, 0x16], 4 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("MX25L6433F", [0xC2, 0x20, 0x17], 8 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new(
+        "MX25L12835F",
+        [0xC2, 0x20, 0x18],
+        16 * M,
+        QuadEnable::Sr1Bit6,
+    )
+    .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new(
+        "MX25L25645G",
+        [0xC2, 0x20, 0x19],
+        32 * M,
+        QuadEnable::Sr1Bit6,
+    )
+    .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new(
+        "MX66L51235F",
+        [0xC2, 0x20, 0x1A],
+        64 * M,
+        QuadEnable::Sr1Bit6,
+    )
+    .with_quirks(Quirks::VOLATILE_WREN),
+    // ISSI
+    Part::new("IS25LP016", [0x9D, 0x60, 0x15], 2 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25LP032", [0x9D, 0x60, 0x16], 4 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25LP064", [0x9D, 0x60, 0x17], 8 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25LP128", [0x9D, 0x60, 0x18], 16 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25LP256", [0x9D, 0x60, 0x19], 32 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25WP064", [0x9D, 0x70, 0x17], 8 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("IS25WP128", [0x9D, 0x70, 0x18], 16 * M, QuadEnable::Sr1Bit6)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    // Micron, quad I/O needs no QE bit
+    Part::new("N25Q032", [0x20, 0xBA, 0x16], 4 * M, QuadEnable::None).with_quirks(Quirks::N25Q),
+    Part::new("N25Q064", [0x20, 0xBA, 0x17], 8 * M, QuadEnable::None).with_quirks(Quirks::N25Q),
+    Part::new("N25Q128", [0x20, 0xBA, 0x18], 16 * M, QuadEnable::None).with_quirks(Quirks::N25Q),
+    // MT25QL256 shares its ID and command set subset with N25Q256
+    Part::new(
+        "N25Q256/MT25QL256",
+        [0x20, 0xBA, 0x19],
+        32 * M,
+        QuadEnable::None,
+    )
+    .with_quirks(Quirks::N25Q),
+    Part::new("MT25QL512", [0x20, 0xBA, 0x20], 64 * M, QuadEnable::None)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("MT25QL01G", [0x20, 0xBA, 0x21], 128 * M, QuadEnable::None)
+        .with_quirks(Quirks::VOLATILE_WREN),
+    Part::new("N25Q128A11", [0x20, 0xBB, 0x18], 16 * M, QuadEnable::None).with_quirks(Quirks::N25Q),
+    Part::new("MT25QU256", [0x20, 0xBB, 0x19], 32 * M, QuadEnable::None).with_quirks(Quirks::N25Q),
+    // Puya
+    Part::new("P25Q16H", [0x85, 0x60, 0x15], 2 * M, QuadEnable::Sr2Bit1),
+    Part::new("P25Q32H", [0x85, 0x60, 0x16], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("P25Q64H", [0x85, 0x60, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new("P25Q128H", [0x85, 0x60, 0x18], 16 * M, QuadEnable::Sr2Bit1),
+    // XMC, sharing the 0x20 manufacturer ID with Micron
+    Part::new("XM25QH64A", [0x20, 0x70, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new("XM25QH64C", [0x20, 0x40, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new(
+        "XM25QH128C",
+        [0x20, 0x40, 0x18],
+        16 * M,
+        QuadEnable::Sr2Bit1,
+    ),
+    Part::new(
+        "XM25QH256C",
+        [0x20, 0x40, 0x19],
+        32 * M,
+        QuadEnable::Sr2Bit1,
+    ),
+    // Zbit
+    Part::new("ZB25VQ16", [0x5E, 0x40, 0x15], 2 * M, QuadEnable::Sr2Bit1),
+    Part::new("ZB25VQ32", [0x5E, 0x40, 0x16], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("ZB25VQ64", [0x5E, 0x40, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new("ZB25VQ128", [0x5E, 0x40, 0x18], 16 * M, QuadEnable::Sr2Bit1),
+    // Adesto
+    Part::new("AT25SF041", [0x1F, 0x84, 0x01], M / 2, QuadEnable::Sr2Bit1),
+    Part::new("AT25SF081", [0x1F, 0x85, 0x01], M, QuadEnable::Sr2Bit1),
+    Part::new("AT25SF161", [0x1F, 0x86, 0x01], 2 * M, QuadEnable::Sr2Bit1),
+    Part::new("AT25SF321", [0x1F, 0x87, 0x01], 4 * M, QuadEnable::Sr2Bit1),
+    Part::new("AT25SF641", [0x1F, 0x32, 0x17], 8 * M, QuadEnable::Sr2Bit1),
+    Part::new(
+        "AT25SF128A",
+        [0x1F, 0x89, 0x01],
+        16 * M,
+        QuadEnable::Sr2Bit1,
+    ),
+];
+
+/// Look up a part by its JEDEC ID
+pub fn find(jedec_id: [u8; 3]) -> Option<&'static Part> {
+    PARTS.iter().find(|part| part.jedec_id == jedec_id)
+}
@@ -1,44 +1,249 @@
-use core::fmt::Error;
-
 pub trait SerialInterface {
-    fn write(&mut self, cmd: &[u8], cmd: Option<&[u8]>)->Result<(), Error>;
-    fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8])->Result<(), Error>;
+    type Error;
+
+    fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error>;
+    fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error>;
     fn delay(&mut self, ms: u32);
+    /// Used to poll short operations, rounds up to whole milliseconds unless overridden
+    fn delay_us(&mut self, us: u32) {
+        self.delay(us.div_ceil(1000));
+    }
+
+    /// Whether `command_read` can put the address or data phase on `width` lines
+    fn supports_width(&self, width: BusWidth) -> bool {
+        width == BusWidth::Single
+    }
+
+    /// Read with a command whose phases may span several lines
+    ///
+    /// Only called with widths accepted by `supports_width`. The default sends a
+    /// single-line command through `write_and_read`, dummy cycles rounded up to bytes.
+    fn command_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let mut bytes = [0_u8; 38];
+        bytes[0] = cmd.opcode;
+        let mut len = 1;
+        if let Some(address) = cmd.address {
+            for i in (0..cmd.address_len as usize).rev() {
+                bytes[len] = (address >> (i * 8)) as u8;
+                len += 1;
+            }
+        }
+        if let Some(mode_bits) = cmd.mode_bits {
+            bytes[len] = mode_bits;
+            len += 1;
+        }
+        len += cmd.dummy_cycles.div_ceil(8) as usize;
+        self.write_and_read(&bytes[..len], buffer)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusWidth {
+    Single,
+    Dual,
+    Quad,
+}
+
+/// Phases of a multi-line command
+///
+/// `address_len` is in bytes; mode bits and address share `address_width`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuadCommand {
+    pub opcode: u8,
+    pub opcode_width: BusWidth,
+    pub address: Option<u32>,
+    pub address_len: u8,
+    pub address_width: BusWidth,
+    pub mode_bits: Option<u8>,
+    pub dummy_cycles: u8,
+    pub data_width: BusWidth,
+}
+
+#[cfg(feature = "qspi")]
+pub trait QuadSerialInterface: SerialInterface {
+    fn quad_write(&mut self, cmd: &QuadCommand, data: Option<&[u8]>) -> Result<(), Self::Error>;
+    fn quad_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    /// Width of every phase used by `write` and `write_and_read`, `Quad` while in QPI mode
+    fn set_command_width(&mut self, width: BusWidth);
+}
+
+/// Controller that can map the flash into the address space, like STM32 QUADSPI or RP2040 XIP
+///
+/// While mapped, the controller issues `cmd` for every access on its own and `write`
+/// or `write_and_read` must not be used.
+pub trait MemoryMappedInterface: SerialInterface {
+    /// Switch to memory-mapped mode, `cmd` has no address as the controller supplies it
+    fn enter_memory_mapped(&mut self, cmd: &QuadCommand) -> Result<(), Self::Error>;
+    /// Back to indirect mode for command-style transfers
+    fn exit_memory_mapped(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Octal (8-8-8) command, every phase on eight lines
+///
+/// The address is always 4 bytes. With `dtr` set, data is sampled on both clock edges.
+#[cfg(feature = "ospi")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OctalCommand {
+    pub opcode: u8,
+    /// Second opcode byte, sent right after `opcode`
+    pub extension: Option<u8>,
+    pub address: Option<u32>,
+    pub dummy_cycles: u8,
+    pub dtr: bool,
+}
+
+#[cfg(feature = "ospi")]
+pub trait OctalSerialInterface: SerialInterface {
+    fn octal_write(&mut self, cmd: &OctalCommand, data: Option<&[u8]>) -> Result<(), Self::Error>;
+    fn octal_read(&mut self, cmd: &OctalCommand, buffer: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+#[cfg(feature = "async")]
+#[allow(async_fn_in_trait)]
+pub trait AsyncSerialInterface {
+    type Error;
+
+    async fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error>;
+    async fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error>;
+    async fn delay(&mut self, ms: u32);
+    /// Used to poll short operations, rounds up to whole milliseconds unless overridden
+    async fn delay_us(&mut self, us: u32) {
+        self.delay(us.div_ceil(1000)).await;
+    }
+}
+
+#[cfg(feature = "spi")]
+pub use embedded_hal_spi::EmbeddedHalSPI;
+
+#[cfg(feature = "async")]
+pub use embedded_hal_async_spi::EmbeddedHalAsyncSPI;
+
+#[cfg(feature = "spi")]
+mod embedded_hal_spi {
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal::spi::{Operation, SpiDevice};
+
+    use super::SerialInterface;
+
+    /// `SerialInterface` on top of an embedded-hal 1.0 `SpiDevice`
+    ///
+    /// Command and data phases are issued as one transaction so chip-select
+    /// stays asserted for the whole command.
+    pub struct EmbeddedHalSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        spi: SPI,
+        delay: D,
+    }
+
+    impl<SPI, D> EmbeddedHalSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        pub fn new(spi: SPI, delay: D) -> Self {
+            Self { spi, delay }
+        }
+
+        pub fn release(self) -> (SPI, D) {
+            (self.spi, self.delay)
+        }
+    }
+
+    impl<SPI, D> SerialInterface for EmbeddedHalSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        type Error = SPI::Error;
+
+        fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error> {
+            match data {
+                Some(data) => self
+                    .spi
+                    .transaction(&mut [Operation::Write(cmd), Operation::Write(data)]),
+                None => self.spi.transaction(&mut [Operation::Write(cmd)]),
+            }
+        }
+
+        fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error> {
+            self.spi
+                .transaction(&mut [Operation::Write(cmd), Operation::Read(rev)])
+        }
+
+        fn delay(&mut self, ms: u32) {
+            self.delay.delay_ms(ms);
+        }
+
+        fn delay_us(&mut self, us: u32) {
+            self.delay.delay_us(us);
+        }
+    }
 }
 
-// struct EmbeddedHalSPI<SPI, Pin>
-// where
-//     SPI: Transfer<u8> + Write<u8>,
-//     Pin: OutputPin,
-// {
-//     spi: SPI,
-//     cs_pin: Pin,
-// }
-
-// impl<SPI, Pin> EmbeddedHalSPI<SPI, Pin>
-// where
-//     SPI: Transfer<u8> + Write<u8>,
-//     Pin: OutputPin,
-// {
-//     pub fn new(spi: SPI, cs_pin: Pin) -> Self {
-//         Self { spi, cs_pin }
-//     }
-// }
-
-// impl<SPI, Pin> SerialInterface for EmbeddedHalSPI<SPI, Pin>
-// where
-//     SPI: Transfer<u8> + Write<u8>,
-//     Pin: OutputPin,
-// {
-//     fn write(&self, data: &[u8])->Result<(), Error> {
-//         self.cs_pin.set_low().unwrap();
-//         self.spi.write(data).unwrap();
-//         self.cs_pin.set_high().unwrap();
-//     }
-
-//     fn read(&self, buffer: &mut [u8], len: usize)->Result<(), Error> {
-//         self.cs_pin.set_low().unwrap();
-//         self.spi.transfer(buffer).unwrap();
-//         self.cs_pin.set_high().unwrap();
-//     }
-// }
\ No newline at end of file
+#[cfg(feature = "async")]
+mod embedded_hal_async_spi {
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_hal_async::spi::{Operation, SpiDevice};
+
+    use super::AsyncSerialInterface;
+
+    /// `AsyncSerialInterface` on top of an embedded-hal-async `SpiDevice`
+    pub struct EmbeddedHalAsyncSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        spi: SPI,
+        delay: D,
+    }
+
+    impl<SPI, D> EmbeddedHalAsyncSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        pub fn new(spi: SPI, delay: D) -> Self {
+            Self { spi, delay }
+        }
+
+        pub fn release(self) -> (SPI, D) {
+            (self.spi, self.delay)
+        }
+    }
+
+    impl<SPI, D> AsyncSerialInterface for EmbeddedHalAsyncSPI<SPI, D>
+    where
+        SPI: SpiDevice,
+        D: DelayNs,
+    {
+        type Error = SPI::Error;
+
+        async fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error> {
+            match data {
+                Some(data) => {
+                    self.spi
+                        .transaction(&mut [Operation::Write(cmd), Operation::Write(data)])
+                        .await
+                }
+                None => self.spi.transaction(&mut [Operation::Write(cmd)]).await,
+            }
+        }
+
+        async fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error> {
+            self.spi
+                .transaction(&mut [Operation::Write(cmd), Operation::Read(rev)])
+                .await
+        }
+
+        async fn delay(&mut self, ms: u32) {
+            self.delay.delay_ms(ms).await;
+        }
+
+        async fn delay_us(&mut self, us: u32) {
+            self.delay.delay_us(us).await;
+        }
+    }
+}
@@ -1,64 +1,770 @@
-use core::fmt::Error;
+use log::{error, info};
+
 use crate::serial_interface::SerialInterface;
+use crate::{
+    AddressMode, EraseType, Error, PowerDownInfo, QuadEnable, ReadCommand, ResetInfo, SoftReset,
+    SuspendInfo, SuspendStatus, Timeouts, define,
+};
+#[cfg(feature = "ospi")]
+use crate::{CommandExtension, OctalEnable, OctalInfo};
+
+/// "SFDP" in little-endian byte order
+const SFDP_SIGNATURE: [u8; 4] = [0x53, 0x46, 0x44, 0x50];
+const HEADER_SIZE: u32 = 8;
+const BASIC_PARAMETER_ID: u16 = 0xFF00;
+#[cfg(feature = "ospi")]
+const XSPI_PROFILE_ID: u16 = 0xFF05;
+const FOUR_BYTE_INSTRUCTION_ID: u16 = 0xFF84;
+/// JESD216F defines 23 DWORDs for the Basic Flash Parameter Table
+const BFPT_MAX_DWORDS: usize = 23;
+
+/// Address bytes supported by the flash (BFPT DWORD 1 bits 18:17)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressBytes {
+    Three,
+    ThreeOrFour,
+    Four,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ParameterHeader {
+    pub id: u16,
+    pub major_rev: u8,
+    pub minor_rev: u8,
+    /// Table length in DWORDs
+    pub length: u8,
+    pub pointer: u32,
+}
+
+impl ParameterHeader {
+    fn parse(buff: &[u8; 8]) -> Self {
+        ParameterHeader {
+            id: u16::from_le_bytes([buff[0], buff[7]]),
+            minor_rev: buff[1],
+            major_rev: buff[2],
+            length: buff[3],
+            pointer: u32::from_le_bytes([buff[4], buff[5], buff[6], 0]),
+        }
+    }
+}
+
+/// Parsed JEDEC Basic Flash Parameter Table
+pub struct SFDPInfo {
+    pub(crate) major_rev: u8,
+    pub(crate) minor_rev: u8,
+    pub(crate) capacity: usize,
+    pub(crate) page_size: usize,
+    pub(crate) erase_types: [Option<EraseType>; 4],
+    pub(crate) address_mode: AddressMode,
+    pub(crate) quad_enable: QuadEnable,
+    pub(crate) dual_output_read: Option<ReadCommand>,
+    pub(crate) dual_io_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    pub(crate) quad_output_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    pub(crate) quad_io_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    pub(crate) qpi_read: Option<ReadCommand>,
+    #[cfg(feature = "qspi")]
+    pub(crate) qpi_enter: Option<u8>,
+    #[cfg(feature = "qspi")]
+    pub(crate) qpi_exit: Option<u8>,
+    #[cfg(feature = "ospi")]
+    pub(crate) command_extension: CommandExtension,
+    #[cfg(feature = "ospi")]
+    pub(crate) xspi_profile: Option<XspiProfile>,
+    pub(crate) timeouts: Timeouts,
+    /// The status bits are not in SFDP, see `suspend_info`
+    pub(crate) suspend: Option<SuspendInfo>,
+    pub(crate) power_down: Option<PowerDownInfo>,
+    pub(crate) reset: ResetInfo,
+    pub(crate) volatile_write_enable: u8,
+}
+
+/// 8D-8D-8D parameters from the xSPI profile 1.0 table
+#[cfg(feature = "ospi")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XspiProfile {
+    pub read_opcode: u8,
+    pub dummy_cycles: u8,
+    pub status_dummy_cycles: u8,
+    pub status_address: bool,
+}
+
+#[cfg(feature = "ospi")]
+impl XspiProfile {
+    fn parse(dwords: &[u32]) -> Option<Self> {
+        if dwords.len() < 5 {
+            return None;
+        }
+        // Use the dummy cycles of the highest listed frequency: 200, 166, 133, 100 MHz
+        let dummy_cycles = [
+            (dwords[3] >> 7) & 0x1F,
+            (dwords[4] >> 27) & 0x1F,
+            (dwords[4] >> 17) & 0x1F,
+            (dwords[4] >> 7) & 0x1F,
+        ]
+        .into_iter()
+        .find(|&d| d != 0)
+        .unwrap_or(20) as u8;
+
+        Some(XspiProfile {
+            read_opcode: (dwords[0] >> 8) as u8,
+            // Dummy cycles must be even in DTR mode
+            dummy_cycles: dummy_cycles.next_multiple_of(2),
+            status_dummy_cycles: if dwords[0] & (1 << 28) != 0 { 8 } else { 4 },
+            status_address: dwords[0] & (1 << 29) != 0,
+        })
+    }
+}
+
+/// Decode a 16-bit fast read field: dummy clocks, mode clocks, opcode
+fn read_command(field: u32) -> ReadCommand {
+    ReadCommand {
+        opcode: (field >> 8) as u8,
+        mode_clocks: ((field >> 5) & 0b111) as u8,
+        dummy_clocks: (field & 0b1_1111) as u8,
+    }
+}
+
+/// Decode a typical time field into microseconds
+///
+/// The low 5 bits hold a count, the bits above select one of `units`.
+fn typical_time(field: u32, units: &[u32]) -> u32 {
+    let unit = units[(field >> 5) as usize % units.len()];
+    unit.saturating_mul((field & 0x1F) + 1)
+}
+
+/// Maximum time from a typical time and its 4-bit multiplier (BFPT DWORD 10/11 bits 3:0)
+fn max_time(typical: u32, multiplier: u32) -> u32 {
+    typical.saturating_mul(2 * ((multiplier & 0x0F) + 1))
+}
+
+/// Worst case timings from BFPT DWORD 10 and 11, defaults for older tables
+fn parse_timeouts(dwords: &[u32], erase_types: &[Option<EraseType>; 4]) -> Timeouts {
+    let mut timeouts = Timeouts::default();
+    if dwords.len() < 11 {
+        return timeouts;
+    }
+
+    let erase_multiplier = dwords[9] & 0x0F;
+    let sector_size = erase_types.iter().flatten().map(|t| t.size).min();
+    let mut block_erase = None;
+    for (i, erase_type) in erase_types.iter().enumerate() {
+        let Some(erase_type) = erase_type else {
+            continue;
+        };
+        let field = (dwords[9] >> (4 + 7 * i)) & 0x7F;
+        let time = max_time(
+            typical_time(field, &[1_000, 16_000, 128_000, 1_000_000]),
+            erase_multiplier,
+        );
+        if Some(erase_type.size) == sector_size {
+            timeouts.sector_erase = time;
+        } else {
+            block_erase = block_erase.max(Some(time));
+        }
+    }
+    timeouts.block_erase = block_erase.unwrap_or(timeouts.sector_erase);
+
+    let program_multiplier = dwords[10] & 0x0F;
+    timeouts.page_program = max_time(
+        typical_time((dwords[10] >> 8) & 0x3F, &[8, 64]),
+        program_multiplier,
+    );
+    timeouts.chip_erase = max_time(
+        typical_time(
+            (dwords[10] >> 24) & 0x7F,
+            &[16_000, 256_000, 4_000_000, 64_000_000],
+        ),
+        program_multiplier,
+    );
+    timeouts
+}
 
-struct SFDPInfo {
-    manufacturer_id: u8,
-    type_id: u8,
-    capacity_id: u8,
-    size: usize,
-    secter_size: u32,
+/// Decode a latency field into microseconds, units are 128 ns, 1 us, 8 us and 64 us
+fn latency(field: u32) -> u32 {
+    let unit = [128, 1_000, 8_000, 64_000][(field >> 5) as usize & 0b11];
+    (unit * ((field & 0x1F) + 1)).div_ceil(1000)
 }
 
-struct SFDP<I>
+/// Suspend and resume opcodes with their latencies from BFPT DWORD 12 and 13
+fn parse_suspend(dwords: &[u32]) -> Option<SuspendInfo> {
+    let (&dword12, &dword13) = (dwords.get(11)?, dwords.get(12)?);
+    // Bit 31 set means suspend/resume is not supported
+    if dword12 & (1 << 31) != 0 {
+        return None;
+    }
+
+    let suspend_latency = latency((dword12 >> 24) & 0x7F).max(latency((dword12 >> 13) & 0x7F));
+    // Resume to suspend intervals are in units of 64 us
+    let resume_interval = 64 * (((dword12 >> 20) & 0x0F).max((dword12 >> 9) & 0x0F) + 1);
+
+    Some(SuspendInfo {
+        suspend: (dword13 >> 24) as u8,
+        resume: (dword13 >> 16) as u8,
+        suspend_latency,
+        resume_interval,
+        status: SuspendStatus::for_manufacturer(0),
+    })
+}
+
+/// Deep power-down opcodes and exit latency from BFPT DWORD 14
+fn parse_power_down(dwords: &[u32]) -> Option<PowerDownInfo> {
+    let &dword = dwords.get(13)?;
+    // Bit 31 set means deep power-down is not supported
+    if dword & (1 << 31) != 0 {
+        return None;
+    }
+    Some(PowerDownInfo {
+        enter: (dword >> 23) as u8,
+        exit: (dword >> 15) as u8,
+        // tDP is not in SFDP, 3 us covers common parts
+        enter_delay: 3,
+        exit_delay: latency((dword >> 8) & 0x7F),
+    })
+}
+
+/// QPI enter and exit opcodes from BFPT DWORD 15, `None` on tables before JESD216A
+#[cfg(feature = "qspi")]
+fn parse_qpi(dwords: &[u32]) -> (Option<u8>, Option<u8>) {
+    let Some(&dword) = dwords.get(14) else {
+        return (None, None);
+    };
+    let enter = if dword & (0b11 << 4) != 0 {
+        Some(0x38)
+    } else if dword & (1 << 6) != 0 {
+        Some(0x35)
+    } else {
+        None
+    };
+    let exit = if dword & 0b01 != 0 {
+        Some(0xFF)
+    } else if dword & 0b10 != 0 {
+        Some(0xF5)
+    } else {
+        None
+    };
+    (enter, exit)
+}
+
+/// Soft reset and 4-byte exit methods from BFPT DWORD 16
+fn parse_reset(dwords: &[u32]) -> ResetInfo {
+    // Tables before JESD216B do not list them, assume the common 0x66/0x99
+    let Some(&dword) = dwords.get(15) else {
+        return ResetInfo {
+            soft_reset: SoftReset::EnableReset,
+            exit_4byte: true,
+            exit_4byte_wren: false,
+        };
+    };
+    let soft_reset = if dword & (1 << 12) != 0 {
+        SoftReset::EnableReset
+    } else if dword & (1 << 11) != 0 {
+        SoftReset::ResetF0
+    } else {
+        SoftReset::None
+    };
+    ResetInfo {
+        soft_reset,
+        exit_4byte: dword & (0b11 << 14) != 0,
+        exit_4byte_wren: dword & (0b11 << 14) == (0b10 << 14),
+    }
+}
+
+/// Pick the 4-byte addressing method from BFPT DWORD 16 bits 31:24
+///
+/// Methods that keep the chip in 3-byte mode come first, so a 3-byte boot ROM
+/// can still read it after an MCU reset.
+fn parse_address_mode(dwords: &[u32], address_bytes: AddressBytes, capacity: usize) -> AddressMode {
+    if capacity <= 1 << 24 && address_bytes != AddressBytes::Four {
+        return AddressMode::ThreeByte;
+    }
+    let enter = dwords.get(15).map_or(0, |d| (d >> 24) as u8);
+    if address_bytes == AddressBytes::Four || enter & 0b0100_0000 != 0 {
+        AddressMode::Always4Byte
+    } else if enter & 0b0010_0000 != 0 {
+        AddressMode::FourByteOpcodes
+    } else if enter & 0b0000_0100 != 0 {
+        AddressMode::ExtendedAddress
+    } else if enter & 0b0000_1000 != 0 {
+        AddressMode::BankRegister
+    } else if enter & 0b0000_0010 != 0 && enter & 0b0000_0001 == 0 {
+        AddressMode::Enter4Byte { write_enable: true }
+    } else {
+        // Tables before JESD216A do not list the methods, 0xB7 is the common one
+        AddressMode::Enter4Byte {
+            write_enable: false,
+        }
+    }
+}
+
+impl SFDPInfo {
+    fn parse<E>(header: &ParameterHeader, dwords: &[u32]) -> Result<Self, Error<E>> {
+        // JESD216 rev 0 requires at least 9 DWORDs
+        if dwords.len() < 9 {
+            error!(
+                "Basic flash parameter table too short: {} DWORDs",
+                dwords.len()
+            );
+            return Err(Error::InvalidSfdp);
+        }
+
+        let density = dwords[1];
+        let capacity = if density & 0x8000_0000 == 0 {
+            (density as usize + 1) / 8
+        } else {
+            // 2^N bits, N below 3 or beyond the address space is not a real part
+            let Some(capacity) = (density & 0x7FFF_FFFF)
+                .checked_sub(3)
+                .and_then(|n| 1_usize.checked_shl(n))
+            else {
+                error!("Invalid density {:08X} in SFDP", density);
+                return Err(Error::InvalidSfdp);
+            };
+            capacity
+        };
+
+        let address_bytes = match (dwords[0] >> 17) & 0b11 {
+            0b00 => AddressBytes::Three,
+            0b01 => AddressBytes::ThreeOrFour,
+            0b10 => AddressBytes::Four,
+            _ => {
+                error!("Invalid address bytes field in SFDP");
+                return Err(Error::InvalidSfdp);
+            }
+        };
+
+        let mut erase_types = [None; 4];
+        for (i, erase_type) in erase_types.iter_mut().enumerate() {
+            let field = (dwords[7 + i / 2] >> ((i % 2) * 16)) as u16;
+            let size = field as u8;
+            if size == 0 {
+                continue;
+            }
+            match 1_u32.checked_shl(size as u32) {
+                Some(size) => {
+                    *erase_type = Some(EraseType {
+                        size,
+                        opcode: (field >> 8) as u8,
+                    })
+                }
+                None => error!("Ignoring erase type {} of 2^{} bytes", i + 1, size),
+            }
+        }
+        // Fall back to the 4K erase opcode from DWORD 1
+        if erase_types.iter().all(Option::is_none) && dwords[0] & 0b11 == 0b01 {
+            erase_types[0] = Some(EraseType {
+                size: 4096,
+                opcode: (dwords[0] >> 8) as u8,
+            });
+        }
+        if erase_types.iter().all(Option::is_none) {
+            error!("No erase type found in SFDP");
+            return Err(Error::InvalidSfdp);
+        }
+
+        // DWORD 1 bit 3: the BP bits are volatile, bit 4 then selects 0x06 instead of 0x50
+        // for writing them. Non-volatile registers take 0x50 for a volatile write.
+        let volatile_status = dwords[0] & (1 << 3) != 0;
+        let volatile_write_enable = if volatile_status && dwords[0] & (1 << 4) != 0 {
+            define::WriteCmd::WriteEnable as u8
+        } else {
+            define::WriteCmd::VolatileWriteEnable as u8
+        };
+
+        let page_size = if dwords.len() >= 11 {
+            1 << ((dwords[10] >> 4) & 0x0F)
+        } else {
+            256
+        };
+
+        let dual_output_read = (dwords[0] & (1 << 16) != 0).then(|| read_command(dwords[3]));
+        let dual_io_read = (dwords[0] & (1 << 20) != 0).then(|| read_command(dwords[3] >> 16));
+        #[cfg(feature = "qspi")]
+        let quad_output_read = (dwords[0] & (1 << 22) != 0).then(|| read_command(dwords[2] >> 16));
+        #[cfg(feature = "qspi")]
+        let quad_io_read = (dwords[0] & (1 << 21) != 0).then(|| read_command(dwords[2]));
+        #[cfg(feature = "qspi")]
+        let qpi_read = (dwords[4] & (1 << 4) != 0).then(|| read_command(dwords[6] >> 16));
+        #[cfg(feature = "qspi")]
+        let (qpi_enter, qpi_exit) = parse_qpi(dwords);
+
+        // JESD216 rev 0 has no DWORD 15, assume the common SR2 bit 1 location
+        let quad_enable = match dwords.get(14).map(|d| (d >> 20) & 0b111) {
+            Some(0b000) => QuadEnable::None,
+            Some(0b010) => QuadEnable::Sr1Bit6,
+            Some(0b011) => QuadEnable::Sr2Bit7,
+            Some(0b110) => QuadEnable::Sr2Bit1WriteSr2,
+            _ => QuadEnable::Sr2Bit1,
+        };
+
+        #[cfg(feature = "ospi")]
+        let command_extension = match dwords.get(17).map(|d| (d >> 29) & 0b11) {
+            Some(0b01) => CommandExtension::Invert,
+            _ => CommandExtension::Repeat,
+        };
+
+        Ok(SFDPInfo {
+            major_rev: header.major_rev,
+            minor_rev: header.minor_rev,
+            capacity,
+            page_size,
+            erase_types,
+            address_mode: parse_address_mode(dwords, address_bytes, capacity),
+            quad_enable,
+            dual_output_read,
+            dual_io_read,
+            #[cfg(feature = "qspi")]
+            quad_output_read,
+            #[cfg(feature = "qspi")]
+            quad_io_read,
+            #[cfg(feature = "qspi")]
+            qpi_read,
+            #[cfg(feature = "qspi")]
+            qpi_enter,
+            #[cfg(feature = "qspi")]
+            qpi_exit,
+            #[cfg(feature = "ospi")]
+            command_extension,
+            #[cfg(feature = "ospi")]
+            xspi_profile: None,
+            timeouts: parse_timeouts(dwords, &erase_types),
+            suspend: parse_suspend(dwords),
+            power_down: parse_power_down(dwords),
+            reset: parse_reset(dwords),
+            volatile_write_enable,
+        })
+    }
+
+    /// Octal parameters, for manufacturers whose octal enable sequence is known
+    #[cfg(feature = "ospi")]
+    pub(crate) fn octal_info(&self, manufacturer_id: u8) -> Option<OctalInfo> {
+        let profile = self.xspi_profile?;
+        let enable = match manufacturer_id {
+            0xC2 => OctalEnable::Macronix,
+            0x2C | 0x9D => OctalEnable::Micron,
+            _ => return None,
+        };
+        Some(OctalInfo {
+            enable,
+            extension: self.command_extension,
+            read_opcode: profile.read_opcode,
+            dummy_cycles: profile.dummy_cycles,
+            status_dummy_cycles: profile.status_dummy_cycles,
+            status_address: profile.status_address,
+        })
+    }
+
+    pub(crate) fn suspend_info(&self, manufacturer_id: u8) -> Option<SuspendInfo> {
+        Some(SuspendInfo {
+            status: SuspendStatus::for_manufacturer(manufacturer_id),
+            ..self.suspend?
+        })
+    }
+
+    /// Smallest supported erase size
+    pub(crate) fn sector_size(&self) -> u32 {
+        self.erase_types
+            .iter()
+            .flatten()
+            .map(|t| t.size)
+            .min()
+            .unwrap_or(4096)
+    }
+}
+
+pub struct SFDP<'a, I>
 where
     I: SerialInterface,
 {
     cmd: [u8; 5],
-    interface: I,
+    interface: &'a mut I,
 }
 
-impl<I> SFDP<I>
+impl<'a, I> SFDP<'a, I>
 where
     I: SerialInterface,
 {
+    pub fn new(interface: &'a mut I) -> Self {
+        SFDP {
+            cmd: [0x5A, 0, 0, 0, 0xff],
+            interface,
+        }
+    }
 
-    fn read_sfdp_data(
-        &mut self,
-        interface: &mut I,
-        address: u32,
-        buffer: &mut [u8],
-    ) -> Result<(), Error> {
-        // Read SFDP data from the specified address
-        // self.cmd[0] = 0x5A; // SFDP command
+    fn read_sfdp_data(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Error<I::Error>> {
+        // Read SFDP data from the specified address, followed by a dummy byte
         self.cmd[1] = (address >> 16) as u8;
         self.cmd[2] = (address >> 8) as u8;
         self.cmd[3] = address as u8;
-        // self.cmd[4] = 0xFF; // Dummy byte
 
-        interface.write_and_read(&self.cmd, buffer)
+        self.interface
+            .write_and_read(&self.cmd, buffer)
+            .map_err(Error::Interface)
     }
 
-    pub fn new(interface: I) -> Self {
-        SFDP {
-            cmd: [0x5A, 0, 0, 0, 0xff],
-            interface: interface,
+    /// Walk the parameter headers and return the first one matching `id`
+    pub fn find_parameter(&mut self, id: u16) -> Result<Option<ParameterHeader>, Error<I::Error>> {
+        let mut header = [0_u8; 8];
+        self.read_sfdp_data(0, &mut header)?;
+
+        if header[..4] != SFDP_SIGNATURE {
+            error!(
+                "Invalid SFDP signature: {:02X} {:02X} {:02X} {:02X}",
+                header[0], header[1], header[2], header[3]
+            );
+            return Err(Error::InvalidSfdp);
+        }
+        info!("SFDP revision {}.{}", header[5], header[4]);
+
+        let count = header[6] as u32 + 1;
+        for i in 0..count {
+            let mut buff = [0_u8; 8];
+            self.read_sfdp_data(HEADER_SIZE * (i + 1), &mut buff)?;
+            let parameter = ParameterHeader::parse(&buff);
+            if parameter.id == id {
+                return Ok(Some(parameter));
+            }
         }
+        Ok(None)
     }
 
-    // pub fn create(&self) -> Option<SFDPInfo> {
-    //     // Read SFDP header
-    //     let mut header = [0; 4];
-    //     self.interface.read(&mut header, 4).ok()?;
+    /// Read a parameter table into `dwords`, returning the number of DWORDs read
+    pub fn read_parameter(
+        &mut self,
+        parameter: &ParameterHeader,
+        dwords: &mut [u32],
+    ) -> Result<usize, Error<I::Error>> {
+        let len = dwords.len().min(parameter.length as usize);
+        for (i, dword) in dwords[..len].iter_mut().enumerate() {
+            let mut buff = [0_u8; 4];
+            self.read_sfdp_data(parameter.pointer + i as u32 * 4, &mut buff)?;
+            *dword = u32::from_le_bytes(buff);
+        }
+        Ok(len)
+    }
 
-    //     // Check if the header is valid
-    //     if header[0] != 0x53 || header[1] != 0x46 || header[2] != 0x44 || header[3] != 0x50 {
-    //         return None;
-    //     }
+    pub fn create(&mut self) -> Result<SFDPInfo, Error<I::Error>> {
+        let Some(parameter) = self.find_parameter(BASIC_PARAMETER_ID)? else {
+            error!("Basic flash parameter table not found");
+            return Err(Error::InvalidSfdp);
+        };
 
-    //     // Read the rest of the SFDP data
-    //     // ...
+        let mut dwords = [0_u32; BFPT_MAX_DWORDS];
+        let len = self.read_parameter(&parameter, &mut dwords)?;
+        let mut info = SFDPInfo::parse(&parameter, &dwords[..len])?;
 
-    //     Some(sfdp_info)
-    // }
+        #[cfg(feature = "ospi")]
+        if let Some(parameter) = self.find_parameter(XSPI_PROFILE_ID)? {
+            let mut dwords = [0_u32; 5];
+            let len = self.read_parameter(&parameter, &mut dwords)?;
+            info.xspi_profile = XspiProfile::parse(&dwords[..len]);
+        }
+
+        // DWORD 1 bits 12:9 tell which erase types have a 4-byte opcode
+        if info.address_mode == AddressMode::FourByteOpcodes
+            && let Some(parameter) = self.find_parameter(FOUR_BYTE_INSTRUCTION_ID)?
+        {
+            let mut dwords = [0_u32; 1];
+            if self.read_parameter(&parameter, &mut dwords)? == 1 {
+                for (i, erase_type) in info.erase_types.iter_mut().enumerate() {
+                    if dwords[0] & (1 << (9 + i)) == 0 {
+                        *erase_type = None;
+                    }
+                }
+            }
+        }
+
+        info!(
+            "SFDP basic table v{}.{}: capacity {} bytes, page {} bytes",
+            info.major_rev, info.minor_rev, info.capacity, info.page_size
+        );
+        Ok(info)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Basic flash parameter table of the W25Q128JV
+    const W25Q128JV: [u32; 16] = [
+        0xFFF9_20E5,
+        0x07FF_FFFF,
+        0x6B08_EB44,
+        0xBB42_3B08,
+        0xFFFF_FFFE,
+        0x0000_FFFF,
+        0xEB40_FFFF,
+        0x520F_200C,
+        0x0000_D810,
+        0x00A6_0236,
+        0xC914_EA82,
+        0x3376_63E9,
+        0x757A_757A,
+        0x5CD5_A2F7,
+        0xFF4D_F719,
+        0xA5F9_70E9,
+    ];
+
+    fn parse(dwords: &[u32]) -> Result<SFDPInfo, Error<()>> {
+        let header = ParameterHeader {
+            id: BASIC_PARAMETER_ID,
+            major_rev: 1,
+            minor_rev: 6,
+            length: dwords.len() as u8,
+            pointer: 0x80,
+        };
+        SFDPInfo::parse(&header, dwords)
+    }
+
+    #[test]
+    fn winbond_bfpt() {
+        let info = parse(&W25Q128JV).unwrap();
+        assert_eq!(info.capacity, 16 * 1024 * 1024);
+        assert_eq!(info.page_size, 256);
+        assert_eq!(
+            info.erase_types,
+            [
+                Some(EraseType {
+                    size: 4096,
+                    opcode: 0x20
+                }),
+                Some(EraseType {
+                    size: 32 * 1024,
+                    opcode: 0x52
+                }),
+                Some(EraseType {
+                    size: 64 * 1024,
+                    opcode: 0xD8
+                }),
+                None,
+            ]
+        );
+        assert_eq!(info.address_mode, AddressMode::ThreeByte);
+        assert_eq!(info.quad_enable, QuadEnable::Sr2Bit1);
+    }
+
+    #[test]
+    fn volatile_write_enable() {
+        let enable = |low_bits: u32| {
+            let mut dwords = W25Q128JV;
+            dwords[0] = (dwords[0] & !(0b11 << 3)) | (low_bits << 3);
+            parse(&dwords).unwrap().volatile_write_enable
+        };
+        assert_eq!(enable(0b00), 0x50);
+        assert_eq!(enable(0b01), 0x50);
+        assert_eq!(enable(0b11), 0x06);
+        // Bit 4 only applies to volatile registers
+        assert_eq!(enable(0b10), 0x50);
+    }
+
+    #[test]
+    fn density_in_bits_or_power_of_two() {
+        let mut dwords = W25Q128JV;
+        // 2^33 bits
+        dwords[1] = 0x8000_0000 | 33;
+        assert_eq!(parse(&dwords).unwrap().capacity, 1 << 30);
+
+        dwords[1] = 0x0FFF_FFFF;
+        assert_eq!(parse(&dwords).unwrap().capacity, 32 * 1024 * 1024);
+
+        for exponent in [0, 2, 0x7FFF_FFFF] {
+            dwords[1] = 0x8000_0000 | exponent;
+            assert!(matches!(parse(&dwords), Err(Error::InvalidSfdp)));
+        }
+    }
+
+    #[test]
+    fn oversized_erase_type_is_ignored() {
+        let mut dwords = W25Q128JV;
+        // Erase type 4: 2^40 bytes
+        dwords[8] |= 0xDC28 << 16;
+        let info = parse(&dwords).unwrap();
+        assert_eq!(info.erase_types[3], None);
+        assert_eq!(info.erase_types[2].map(|t| t.size), Some(64 * 1024));
+    }
+
+    #[test]
+    fn erase_type_fallback_to_dword1() {
+        let mut dwords = W25Q128JV;
+        dwords[7] = 0;
+        dwords[8] = 0;
+        let info = parse(&dwords).unwrap();
+        assert_eq!(
+            info.erase_types,
+            [
+                Some(EraseType {
+                    size: 4096,
+                    opcode: 0x20
+                }),
+                None,
+                None,
+                None,
+            ]
+        );
+
+        dwords[0] |= 0b11;
+        assert!(matches!(parse(&dwords), Err(Error::InvalidSfdp)));
+    }
+
+    #[test]
+    fn four_byte_methods() {
+        const CAPACITY: usize = 32 * 1024 * 1024;
+        let mode = |enter: u32, address_bytes| {
+            let mut dwords = W25Q128JV;
+            dwords[15] = (dwords[15] & 0x00FF_FFFF) | (enter << 24);
+            parse_address_mode(&dwords, address_bytes, CAPACITY)
+        };
+
+        assert_eq!(
+            parse_address_mode(&W25Q128JV, AddressBytes::Three, 16 * 1024 * 1024),
+            AddressMode::ThreeByte
+        );
+        assert_eq!(
+            mode(0b0010_0001, AddressBytes::ThreeOrFour),
+            AddressMode::FourByteOpcodes
+        );
+        assert_eq!(
+            mode(0b0000_0100, AddressBytes::ThreeOrFour),
+            AddressMode::ExtendedAddress
+        );
+        assert_eq!(
+            mode(0b0000_1000, AddressBytes::ThreeOrFour),
+            AddressMode::BankRegister
+        );
+        assert_eq!(
+            mode(0b0000_0010, AddressBytes::ThreeOrFour),
+            AddressMode::Enter4Byte { write_enable: true }
+        );
+        assert_eq!(
+            mode(0b0000_0001, AddressBytes::ThreeOrFour),
+            AddressMode::Enter4Byte {
+                write_enable: false
+            }
+        );
+        assert_eq!(
+            mode(0b0000_0001, AddressBytes::Four),
+            AddressMode::Always4Byte
+        );
+    }
+
+    #[test]
+    fn power_down_from_winbond_dword14() {
+        // W25Q128JV: 0xB9/0xAB, tRES1 of 3 us
+        let mut dwords = W25Q128JV;
+        assert_eq!(
+            parse_power_down(&dwords),
+            Some(PowerDownInfo {
+                enter: 0xB9,
+                exit: 0xAB,
+                enter_delay: 3,
+                exit_delay: 3,
+            })
+        );
+
+        dwords[13] |= 1 << 31;
+        assert_eq!(parse_power_down(&dwords), None);
+    }
+}
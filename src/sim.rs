@@ -0,0 +1,1556 @@
+//! In-memory flash simulator for host-side testing
+//!
+//! `SimulatedFlash` decodes the opcodes from `define` over a byte array with
+//! NOR semantics: erase sets bytes to 0xFF and program can only clear bits.
+
+use std::vec;
+use std::vec::Vec;
+
+use crate::define;
+use crate::serial_interface::{BusWidth, MemoryMappedInterface, QuadCommand, SerialInterface};
+
+const SFDP_BFPT_POINTER: usize = 0x30;
+const SFDP_BFPT_DWORDS: usize = 16;
+/// Dedicated 4-byte address opcodes and their 3-byte equivalents
+const FOUR_BYTE_OPCODES: [(u8, u8); 10] = [
+    (0x13, 0x03),
+    (0x0C, 0x0B),
+    (0x3C, 0x3B),
+    (0xBC, 0xBB),
+    (0x6C, 0x6B),
+    (0xEC, 0xEB),
+    (0x12, 0x02),
+    (0x21, 0x20),
+    (0x5C, 0x52),
+    (0xDC, 0xD8),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimError {
+    /// Opcode not handled by the simulator
+    UnknownCommand(u8),
+    /// Command is shorter than its opcode requires
+    ShortCommand(u8),
+    /// Quad command issued while the QE bit is clear
+    QuadDisabled,
+    /// Command issued in continuous read mode, where the chip takes it as an address
+    ContinuousRead(u8),
+    /// Command-style transfer while the controller is in memory-mapped mode
+    MemoryMapped(u8),
+    /// `mapped_read` outside memory-mapped mode
+    NotMemoryMapped,
+}
+
+pub struct SimulatedFlash {
+    jedec_id: [u8; 3],
+    memory: Vec<u8>,
+    page_size: usize,
+    status: [u8; 3],
+    write_enabled: bool,
+    address_4_byte: bool,
+    /// The command being decoded takes a 4-byte address regardless of the mode
+    opcode_4_byte: bool,
+    /// Extended address or bank register, the top byte of 3-byte addresses
+    extended_address: u8,
+    powered_down: bool,
+    /// M5-M4 of the last I/O read were 10, the next transfer skips the opcode
+    continuous_read: bool,
+    /// Read command template while in memory-mapped mode
+    memory_mapped: Option<QuadCommand>,
+    reset_enabled: bool,
+    volatile_write_enabled: bool,
+    busy_polls: u32,
+    busy_remaining: u32,
+    /// Busy polls left in the suspended operation, SR2 bit 7 is set meanwhile
+    suspended_remaining: u32,
+    /// Individual lock bit per 4K sector, only enforced while WPS is set
+    locks: Vec<bool>,
+    /// Three 256 byte security registers at 0x1000, 0x2000 and 0x3000
+    security: [[u8; 256]; 3],
+    elapsed_us: u64,
+    commands: Vec<u8>,
+    sfdp: Vec<u8>,
+}
+
+impl SimulatedFlash {
+    pub fn new(jedec_id: [u8; 3], capacity: usize) -> Self {
+        let page_size = 256;
+        SimulatedFlash {
+            jedec_id,
+            memory: vec![0xFF; capacity],
+            page_size,
+            status: [0; 3],
+            write_enabled: false,
+            address_4_byte: false,
+            opcode_4_byte: false,
+            extended_address: 0,
+            powered_down: false,
+            continuous_read: false,
+            memory_mapped: None,
+            reset_enabled: false,
+            volatile_write_enabled: false,
+            busy_polls: 0,
+            busy_remaining: 0,
+            suspended_remaining: 0,
+            locks: vec![true; capacity.div_ceil(4096)],
+            security: [[0xFF; 256]; 3],
+            elapsed_us: 0,
+            commands: Vec::new(),
+            sfdp: Self::build_sfdp(capacity, page_size),
+        }
+    }
+
+    /// Report BUSY for `polls` status reads after every program or erase
+    pub fn set_busy_polls(&mut self, polls: u32) {
+        self.busy_polls = polls;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn sfdp_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.sfdp
+    }
+
+    pub fn is_address_4_byte(&self) -> bool {
+        self.address_4_byte
+    }
+
+    /// Extended address or bank register content
+    pub fn extended_address(&self) -> u8 {
+        self.extended_address
+    }
+
+    pub fn is_powered_down(&self) -> bool {
+        self.powered_down
+    }
+
+    pub fn is_continuous_read(&self) -> bool {
+        self.continuous_read
+    }
+
+    pub fn memory_mapped_command(&self) -> Option<QuadCommand> {
+        self.memory_mapped
+    }
+
+    /// Read `address` with the memory-mapped command, like a controller on a bus access
+    ///
+    /// In continuous read mode the opcode is skipped and not recorded.
+    pub fn mapped_read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), SimError> {
+        let Some(mut cmd) = self.memory_mapped else {
+            return Err(SimError::NotMemoryMapped);
+        };
+        if !self.continuous_read {
+            self.commands.push(cmd.opcode);
+        }
+        cmd.address = Some(address);
+        self.multi_line_read(&cmd, buffer)
+    }
+
+    fn check_indirect(&self, opcode: u8) -> Result<(), SimError> {
+        if self.memory_mapped.is_some() {
+            return Err(SimError::MemoryMapped(opcode));
+        }
+        if self.continuous_read {
+            return Err(SimError::ContinuousRead(opcode));
+        }
+        Ok(())
+    }
+
+    /// Total time passed to `delay` and `delay_us`
+    pub fn elapsed_ms(&self) -> u64 {
+        self.elapsed_us / 1000
+    }
+
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_us
+    }
+
+    /// Opcodes received since creation or the last `clear_commands`
+    pub fn commands(&self) -> &[u8] {
+        &self.commands
+    }
+
+    pub fn clear_commands(&mut self) {
+        self.commands.clear();
+    }
+
+    fn build_sfdp(capacity: usize, page_size: usize) -> Vec<u8> {
+        let mut sfdp = vec![0xFF; SFDP_BFPT_POINTER + SFDP_BFPT_DWORDS * 4];
+        // SFDP header: signature, revision 1.6, one parameter header
+        sfdp[..8].copy_from_slice(&[0x53, 0x46, 0x44, 0x50, 0x06, 0x01, 0x00, 0xFF]);
+        // Basic flash parameter header
+        sfdp[8..16].copy_from_slice(&[
+            0x00,
+            0x06,
+            0x01,
+            SFDP_BFPT_DWORDS as u8,
+            SFDP_BFPT_POINTER as u8,
+            0x00,
+            0x00,
+            0xFF,
+        ]);
+
+        let mut dwords = [0_u32; SFDP_BFPT_DWORDS];
+        let address_bytes = if capacity > (1 << 24) { 0b01 } else { 0b00 };
+        dwords[0] = 0xFF80_0000
+            | (address_bytes << 17)
+            | ((define::EraseCmd::Sector4k as u32) << 8)
+            | (1 << 22)
+            | (1 << 21)
+            | (1 << 20)
+            | (1 << 16)
+            | 0b100
+            | 0b01;
+        let bits = capacity as u64 * 8;
+        dwords[1] = if bits <= (1 << 32) {
+            (bits - 1) as u32
+        } else {
+            0x8000_0000 | bits.trailing_zeros()
+        };
+        dwords[7] = ((define::EraseCmd::Block32k as u32) << 24)
+            | (15 << 16)
+            | ((define::EraseCmd::Sector4k as u32) << 8)
+            | 12;
+        dwords[8] = ((define::EraseCmd::Block64k as u32) << 8) | 16;
+        // 1-1-2 0x3B with 8 dummy clocks, 1-2-2 0xBB with 4 mode clocks
+        dwords[3] = (0xBB << 24) | (4 << 21) | (0x3B << 8) | 8;
+        // 1-1-4 0x6B with 8 dummy clocks, 1-4-4 0xEB with 2 mode and 4 dummy clocks
+        dwords[2] = (0x6B << 24) | (8 << 16) | (0xEB << 8) | (2 << 5) | 4;
+        // Typical erase times 48 ms, 128 ms and 256 ms, maximum six times typical
+        dwords[9] = (0x41 << 18) | (0x27 << 11) | (0x22 << 4) | 2;
+        // Typical page program 384 us and chip erase 8 s, maximum six times typical
+        dwords[10] = (0x41 << 24) | (0x25 << 8) | (page_size.trailing_zeros() << 4) | 2;
+        // Suspend/resume with 20 us latency, 0x75 and 0x7A for both erase and program
+        dwords[11] = (0x33 << 24) | (0x33 << 13);
+        dwords[12] = 0x757A_757A;
+        // Deep power-down 0xB9, released by 0xAB after 3 us, status polled through 0x05
+        dwords[13] = (0xB9 << 23) | (0xAB << 15) | (0x22 << 8) | (1 << 2);
+        // Soft reset 0x66/0x99, leave 4-byte address mode with 0xE9
+        dwords[15] = (1 << 14) | (1 << 12);
+        if capacity > (1 << 24) {
+            // Enter 4-byte mode with 0xB7, extended address register, dedicated opcodes
+            dwords[15] |= 0b10_0101 << 24;
+        }
+        // QE is bit 1 of status register 2
+        dwords[14] = 0b100 << 20;
+
+        for (i, dword) in dwords.iter().enumerate() {
+            let offset = SFDP_BFPT_POINTER + i * 4;
+            sfdp[offset..offset + 4].copy_from_slice(&dword.to_le_bytes());
+        }
+        sfdp
+    }
+
+    const fn address_len(&self) -> usize {
+        if self.address_4_byte || self.opcode_4_byte {
+            4
+        } else {
+            3
+        }
+    }
+
+    /// Map dedicated 4-byte opcodes to their 3-byte equivalents
+    fn decode_opcode(&mut self, opcode: u8) -> u8 {
+        match FOUR_BYTE_OPCODES.iter().find(|(four, _)| *four == opcode) {
+            Some(&(_, three)) => {
+                self.opcode_4_byte = true;
+                three
+            }
+            None => {
+                self.opcode_4_byte = false;
+                opcode
+            }
+        }
+    }
+
+    fn parse_address(&self, cmd: &[u8]) -> Result<usize, SimError> {
+        let len = self.address_len();
+        if cmd.len() < len + 1 {
+            return Err(SimError::ShortCommand(cmd[0]));
+        }
+        let mut address = cmd[1..=len]
+            .iter()
+            .fold(0_usize, |addr, &b| (addr << 8) | b as usize);
+        if len == 3 {
+            address |= (self.extended_address as usize) << 24;
+        }
+        Ok(address % self.memory.len())
+    }
+
+    /// Soft reset: volatile state back to power-on values
+    fn reset(&mut self) {
+        self.write_enabled = false;
+        self.address_4_byte = false;
+        self.extended_address = 0;
+        self.busy_remaining = 0;
+        self.suspended_remaining = 0;
+        self.status[1] &= !0x80;
+        self.locks.fill(true);
+    }
+
+    /// Sectors sharing the lock bit of `address`, the outer 64K blocks lock per 4K sector
+    fn lock_sectors(&self, address: usize) -> std::ops::Range<usize> {
+        let sector = address / 4096;
+        if address < 0x10000 || address + 0x10000 >= self.memory.len() {
+            sector..sector + 1
+        } else {
+            let first = sector - sector % 16;
+            first..first + 16
+        }
+    }
+
+    fn is_locked(&self, address: usize, size: usize) -> bool {
+        let wps = self.status[2] & 0x04 != 0;
+        wps && self.locks[address / 4096..(address + size).div_ceil(4096)]
+            .iter()
+            .any(|&locked| locked)
+    }
+
+    fn start_busy(&mut self) {
+        self.busy_remaining = self.busy_polls;
+    }
+
+    fn program(&mut self, address: usize, data: impl Iterator<Item = u8>) {
+        let page_base = address - address % self.page_size;
+        let mut offset = address % self.page_size;
+        for byte in data {
+            self.memory[page_base + offset] &= byte;
+            offset = (offset + 1) % self.page_size;
+        }
+    }
+
+    fn erase(&mut self, address: usize, size: usize) {
+        let start = address - address % size;
+        self.memory[start..start + size].fill(0xFF);
+    }
+
+    /// Security register index and offset of `address`, `None` if unmapped or locked by LB
+    fn security_register(&self, address: usize, writing: bool) -> Option<(usize, usize)> {
+        let index = (address >> 12).checked_sub(1).filter(|&i| i < 3)?;
+        let locked = self.status[1] & (0x08 << index) != 0;
+        (!writing || !locked).then_some((index, address & 0xFF))
+    }
+
+    fn read(&self, address: usize, rev: &mut [u8]) {
+        for (i, byte) in rev.iter_mut().enumerate() {
+            *byte = self.memory[(address + i) % self.memory.len()];
+        }
+    }
+}
+
+impl SerialInterface for SimulatedFlash {
+    type Error = SimError;
+
+    fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error> {
+        let Some(&opcode) = cmd.first() else {
+            return Ok(());
+        };
+        self.commands.push(opcode);
+        let opcode = self.decode_opcode(opcode);
+        if self.memory_mapped.is_some() {
+            return Err(SimError::MemoryMapped(opcode));
+        }
+        // Only the mode bit reset is told apart from an address in continuous read mode
+        if self.continuous_read {
+            if cmd.iter().all(|&b| b == 0xFF) {
+                self.continuous_read = false;
+                return Ok(());
+            }
+            return Err(SimError::ContinuousRead(opcode));
+        }
+
+        const WRITE_ENABLE: u8 = define::WriteCmd::WriteEnable as u8;
+        const WRITE_DISABLE: u8 = define::WriteCmd::WriteDisable as u8;
+        const WRITE_STATUS: u8 = define::WriteCmd::WrietStatus as u8;
+        const WRITE_STATUS_2: u8 = define::WriteCmd::WriteStatus2 as u8;
+        const WRITE_STATUS_3: u8 = define::WriteCmd::WriteStatus3 as u8;
+        const VOLATILE_WRITE_ENABLE: u8 = define::WriteCmd::VolatileWriteEnable as u8;
+        const PAGE_PROGRAM: u8 = define::WriteCmd::PageProgram as u8;
+        const SECTOR_4K: u8 = define::EraseCmd::Sector4k as u8;
+        const BLOCK_32K: u8 = define::EraseCmd::Block32k as u8;
+        const BLOCK_64K: u8 = define::EraseCmd::Block64k as u8;
+        const CHIP: u8 = define::EraseCmd::Chip as u8;
+        const SUSPEND: u8 = define::EraseCmd::Suspend as u8;
+        const RESUME: u8 = define::EraseCmd::Resume as u8;
+        const POWER_DOWN: u8 = define::ModeCmd::PowerDown as u8;
+        const RELEASE_POWER_DOWN: u8 = define::ModeCmd::ReleasePowerDown as u8;
+        const MODE_RESET: u8 = define::ModeCmd::ModeReset as u8;
+        const RESET_ENABLE: u8 = define::ModeCmd::ResetEnable as u8;
+        const RESET: u8 = define::ModeCmd::Reset as u8;
+        const BLOCK_LOCK: u8 = define::LockCmd::BlockLock as u8;
+        const BLOCK_UNLOCK: u8 = define::LockCmd::BlockUnlock as u8;
+        const GLOBAL_LOCK: u8 = define::LockCmd::GlobalLock as u8;
+        const GLOBAL_UNLOCK: u8 = define::LockCmd::GlobalUnlock as u8;
+        const SECURITY_PROGRAM: u8 = define::SecurityCmd::Program as u8;
+        const SECURITY_ERASE: u8 = define::SecurityCmd::Erase as u8;
+
+        // Only the release command is decoded in deep power-down
+        if self.powered_down {
+            if opcode == RELEASE_POWER_DOWN {
+                self.powered_down = false;
+            }
+            return Ok(());
+        }
+
+        // Reset enable only applies to the command right after it
+        let reset_enabled = std::mem::take(&mut self.reset_enabled);
+        // Volatile and non-volatile status bits are not told apart
+        let status_writable =
+            std::mem::take(&mut self.volatile_write_enabled) || self.write_enabled;
+        match opcode {
+            WRITE_ENABLE => self.write_enabled = true,
+            WRITE_DISABLE => self.write_enabled = false,
+            VOLATILE_WRITE_ENABLE => self.volatile_write_enabled = true,
+            WRITE_STATUS => {
+                let mut values = cmd[1..].iter().chain(data.unwrap_or(&[]));
+                if status_writable {
+                    if let Some(&sr1) = values.next() {
+                        self.status[0] = sr1 & 0xFC;
+                    }
+                    if let Some(&sr2) = values.next() {
+                        // LB bits are one-time programmable
+                        self.status[1] = sr2 | (self.status[1] & 0x38);
+                    }
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            PAGE_PROGRAM => {
+                let address = self.parse_address(cmd)?;
+                if self.write_enabled && !self.is_locked(address, 1) {
+                    let payload = cmd[1 + self.address_len()..]
+                        .iter()
+                        .chain(data.unwrap_or(&[]))
+                        .copied()
+                        .collect::<Vec<u8>>();
+                    self.program(address, payload.into_iter());
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            SECTOR_4K | BLOCK_32K | BLOCK_64K => {
+                let address = self.parse_address(cmd)?;
+                let size = match opcode {
+                    SECTOR_4K => 4 * 1024,
+                    BLOCK_32K => 32 * 1024,
+                    _ => 64 * 1024,
+                };
+                let size = size.min(self.memory.len());
+                if self.write_enabled && !self.is_locked(address - address % size, size) {
+                    self.erase(address, size);
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            CHIP | 0x60 => {
+                if self.write_enabled && !self.is_locked(0, self.memory.len()) {
+                    self.memory.fill(0xFF);
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            WRITE_STATUS_2 | WRITE_STATUS_3 => {
+                if status_writable {
+                    if let Some(&value) = cmd.get(1).or(data.and_then(|d| d.first())) {
+                        if opcode == WRITE_STATUS_2 {
+                            self.status[1] = value | (self.status[1] & 0x38);
+                        } else {
+                            self.status[2] = value;
+                        }
+                    }
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            SUSPEND => {
+                if self.busy_remaining > 0 {
+                    self.suspended_remaining = self.busy_remaining;
+                    self.busy_remaining = 0;
+                    self.status[1] |= 0x80;
+                }
+            }
+            RESUME => {
+                if self.status[1] & 0x80 != 0 {
+                    self.status[1] &= !0x80;
+                    self.busy_remaining = self.suspended_remaining;
+                }
+            }
+            MODE_RESET => {}
+            RESET_ENABLE => self.reset_enabled = true,
+            RESET => {
+                if reset_enabled {
+                    self.reset();
+                }
+            }
+            BLOCK_LOCK | BLOCK_UNLOCK => {
+                let address = self.parse_address(cmd)?;
+                if self.write_enabled {
+                    let sectors = self.lock_sectors(address);
+                    self.locks[sectors].fill(opcode == BLOCK_LOCK);
+                }
+                self.write_enabled = false;
+            }
+            SECURITY_PROGRAM => {
+                let address = self.parse_address(cmd)?;
+                if let (true, Some((index, offset))) =
+                    (self.write_enabled, self.security_register(address, true))
+                {
+                    let payload = cmd[1 + self.address_len()..]
+                        .iter()
+                        .chain(data.unwrap_or(&[]));
+                    for (i, byte) in payload.enumerate() {
+                        self.security[index][(offset + i) % 256] &= byte;
+                    }
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            SECURITY_ERASE => {
+                let address = self.parse_address(cmd)?;
+                if let (true, Some((index, _))) =
+                    (self.write_enabled, self.security_register(address, true))
+                {
+                    self.security[index].fill(0xFF);
+                    self.start_busy();
+                }
+                self.write_enabled = false;
+            }
+            GLOBAL_LOCK | GLOBAL_UNLOCK => {
+                if self.write_enabled {
+                    self.locks.fill(opcode == GLOBAL_LOCK);
+                }
+                self.write_enabled = false;
+            }
+            POWER_DOWN => self.powered_down = true,
+            RELEASE_POWER_DOWN => {}
+            0xB7 => self.address_4_byte = true,
+            0xE9 => self.address_4_byte = false,
+            // Extended address register and bank register
+            0xC5 | 0x17 => {
+                if cmd.len() < 2 {
+                    return Err(SimError::ShortCommand(opcode));
+                }
+                if self.write_enabled {
+                    self.extended_address = cmd[1];
+                }
+                self.write_enabled = false;
+            }
+            _ => return Err(SimError::UnknownCommand(opcode)),
+        }
+        Ok(())
+    }
+
+    fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error> {
+        let Some(&opcode) = cmd.first() else {
+            return Ok(());
+        };
+        self.commands.push(opcode);
+        let opcode = self.decode_opcode(opcode);
+        self.check_indirect(opcode)?;
+
+        const JEDEC_ID: u8 = define::IdCmd::JedecId as u8;
+        const STATUS_1: u8 = define::ReadCmd::Status1 as u8;
+        const STATUS_2: u8 = define::ReadCmd::Status2 as u8;
+        const STATUS_3: u8 = define::ReadCmd::Status3 as u8;
+        const DATA: u8 = define::ReadCmd::Data as u8;
+        const FAST: u8 = define::ReadCmd::Fast as u8;
+        const READ_BLOCK_LOCK: u8 = define::LockCmd::ReadBlockLock as u8;
+        const READ_UNIQUE: u8 = define::IdCmd::ReadUnique as u8;
+        const DEVICE_ID: u8 = define::IdCmd::DeviceId as u8;
+        const MANUFACTURER: u8 = define::IdCmd::Manufacturer as u8;
+        const SECURITY_READ: u8 = define::SecurityCmd::Read as u8;
+
+        // Nothing drives the data line in deep power-down
+        if self.powered_down {
+            if opcode == define::ModeCmd::ReleasePowerDown as u8 {
+                self.powered_down = false;
+            }
+            rev.fill(0xFF);
+            return Ok(());
+        }
+
+        match opcode {
+            JEDEC_ID => {
+                for (byte, id) in rev.iter_mut().zip(self.jedec_id.iter().cycle()) {
+                    *byte = *id;
+                }
+            }
+            STATUS_1 => {
+                let mut status = self.status[0];
+                if self.write_enabled {
+                    status |= define::STATUS::WEL as u8;
+                }
+                if self.busy_remaining > 0 {
+                    self.busy_remaining -= 1;
+                    status |= define::STATUS::BUSY as u8;
+                }
+                rev.fill(status);
+            }
+            STATUS_2 => rev.fill(self.status[1]),
+            STATUS_3 => rev.fill(self.status[2]),
+            DATA => {
+                let address = self.parse_address(cmd)?;
+                self.read(address, rev);
+            }
+            FAST => {
+                if cmd.len() < self.address_len() + 2 {
+                    return Err(SimError::ShortCommand(opcode));
+                }
+                let address = self.parse_address(cmd)?;
+                self.read(address, rev);
+            }
+            READ_BLOCK_LOCK => {
+                let address = self.parse_address(cmd)?;
+                rev.fill(self.locks[address / 4096] as u8);
+            }
+            // Winbond style device ID, one below the capacity code
+            DEVICE_ID => rev.fill(self.jedec_id[2].wrapping_sub(1)),
+            MANUFACTURER => {
+                let id = [self.jedec_id[0], self.jedec_id[2].wrapping_sub(1)];
+                for (byte, id) in rev.iter_mut().zip(id.iter().cycle()) {
+                    *byte = *id;
+                }
+            }
+            READ_UNIQUE => {
+                if cmd.len() < self.address_len() + 2 {
+                    return Err(SimError::ShortCommand(opcode));
+                }
+                // Derived from the JEDEC ID so different parts read back different IDs
+                let id = [
+                    0xD2,
+                    0x63,
+                    0x48,
+                    0x17,
+                    0x0B,
+                    self.jedec_id[0],
+                    self.jedec_id[1],
+                    self.jedec_id[2],
+                ];
+                for (byte, id) in rev.iter_mut().zip(id.iter().cycle()) {
+                    *byte = *id;
+                }
+            }
+            SECURITY_READ => {
+                if cmd.len() < self.address_len() + 2 {
+                    return Err(SimError::ShortCommand(opcode));
+                }
+                let address = self.parse_address(cmd)?;
+                match self.security_register(address, false) {
+                    Some((index, offset)) => {
+                        for (i, byte) in rev.iter_mut().enumerate() {
+                            *byte = self.security[index][(offset + i) % 256];
+                        }
+                    }
+                    None => rev.fill(0xFF),
+                }
+            }
+            0x5A => {
+                if cmd.len() < 5 {
+                    return Err(SimError::ShortCommand(opcode));
+                }
+                let address = cmd[1..4]
+                    .iter()
+                    .fold(0_usize, |addr, &b| (addr << 8) | b as usize);
+                for (i, byte) in rev.iter_mut().enumerate() {
+                    *byte = self.sfdp.get(address + i).copied().unwrap_or(0xFF);
+                }
+            }
+            _ => return Err(SimError::UnknownCommand(opcode)),
+        }
+        Ok(())
+    }
+
+    fn delay(&mut self, ms: u32) {
+        self.elapsed_us += ms as u64 * 1000;
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.elapsed_us += us as u64;
+    }
+
+    fn supports_width(&self, _width: BusWidth) -> bool {
+        true
+    }
+
+    fn command_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.commands.push(cmd.opcode);
+        self.check_indirect(cmd.opcode)?;
+        self.multi_line_read(cmd, buffer)
+    }
+}
+
+impl SimulatedFlash {
+    fn multi_line_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), SimError> {
+        const DATA: u8 = define::ReadCmd::Data as u8;
+        const FAST: u8 = define::ReadCmd::Fast as u8;
+        const FAST_DUAL: u8 = define::ReadCmd::FastDual as u8;
+        const FAST_DUAL_IO: u8 = define::ReadCmd::FastDualIo as u8;
+        #[cfg(feature = "qspi")]
+        const FAST_QUAD: u8 = define::QuadCmd::FastQuad as u8;
+        #[cfg(feature = "qspi")]
+        const FAST_QUAD_IO: u8 = define::QuadCmd::FastQuadIo as u8;
+
+        let opcode = self.decode_opcode(cmd.opcode);
+        if self.powered_down {
+            buffer.fill(0xFF);
+            return Ok(());
+        }
+
+        // Clocks between the address and data phases
+        let lines = match cmd.address_width {
+            BusWidth::Single => 1,
+            BusWidth::Dual => 2,
+            BusWidth::Quad => 4,
+        };
+        let clocks = cmd.mode_bits.map_or(0, |_| 8 / lines) + cmd.dummy_cycles;
+        let expected = match (opcode, cmd.address_width, cmd.data_width) {
+            (DATA, BusWidth::Single, BusWidth::Single) => 0,
+            (FAST, BusWidth::Single, BusWidth::Single)
+            | (FAST_DUAL, BusWidth::Single, BusWidth::Dual) => 8,
+            (FAST_DUAL_IO, BusWidth::Dual, BusWidth::Dual) => 4,
+            #[cfg(feature = "qspi")]
+            (FAST_QUAD, BusWidth::Single, BusWidth::Quad) => 8,
+            #[cfg(feature = "qspi")]
+            (FAST_QUAD_IO, BusWidth::Quad, BusWidth::Quad) => 6,
+            _ => return Err(SimError::UnknownCommand(cmd.opcode)),
+        };
+        if clocks != expected || cmd.address_len as usize != self.address_len() {
+            return Err(SimError::ShortCommand(cmd.opcode));
+        }
+        if cmd.data_width == BusWidth::Quad && self.status[1] & 0b10 == 0 {
+            return Err(SimError::QuadDisabled);
+        }
+        // Only the I/O reads carry mode bits
+        if let Some(mode_bits) = cmd.mode_bits {
+            self.continuous_read = mode_bits & 0x30 == 0x20;
+        }
+
+        let mut address = cmd.address.unwrap_or(0) as usize;
+        if cmd.address_len == 3 {
+            address |= (self.extended_address as usize) << 24;
+        }
+        self.read(address % self.memory.len(), buffer);
+        Ok(())
+    }
+}
+
+impl MemoryMappedInterface for SimulatedFlash {
+    fn enter_memory_mapped(&mut self, cmd: &QuadCommand) -> Result<(), Self::Error> {
+        self.check_indirect(cmd.opcode)?;
+        if cmd.address.is_some() {
+            return Err(SimError::UnknownCommand(cmd.opcode));
+        }
+        self.memory_mapped = Some(*cmd);
+        Ok(())
+    }
+
+    fn exit_memory_mapped(&mut self) -> Result<(), Self::Error> {
+        self.memory_mapped = None;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "qspi")]
+impl crate::serial_interface::QuadSerialInterface for SimulatedFlash {
+    fn quad_write(&mut self, cmd: &QuadCommand, data: Option<&[u8]>) -> Result<(), Self::Error> {
+        self.commands.push(cmd.opcode);
+        self.check_indirect(cmd.opcode)?;
+        if cmd.opcode != define::QuadCmd::PageProgram as u8
+            && cmd.opcode != define::FourByteCmd::QuadPageProgram as u8
+        {
+            return Err(SimError::UnknownCommand(cmd.opcode));
+        }
+        if self.status[1] & 0b10 == 0 {
+            return Err(SimError::QuadDisabled);
+        }
+        let address = cmd.address.unwrap_or(0) as usize % self.memory.len();
+        if self.write_enabled {
+            self.program(address, data.unwrap_or(&[]).iter().copied());
+            self.start_busy();
+        }
+        self.write_enabled = false;
+        Ok(())
+    }
+
+    fn quad_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.commands.push(cmd.opcode);
+        self.check_indirect(cmd.opcode)?;
+        const FAST_QUAD: u8 = define::QuadCmd::FastQuad as u8;
+        const FAST_QUAD_IO: u8 = define::QuadCmd::FastQuadIo as u8;
+        const FAST_QUAD_4B: u8 = define::FourByteCmd::FastQuad as u8;
+        const FAST_QUAD_IO_4B: u8 = define::FourByteCmd::FastQuadIo as u8;
+        match cmd.opcode {
+            FAST_QUAD | FAST_QUAD_IO | FAST_QUAD_4B | FAST_QUAD_IO_4B => {
+                if self.status[1] & 0b10 == 0 {
+                    return Err(SimError::QuadDisabled);
+                }
+                let address = cmd.address.unwrap_or(0) as usize % self.memory.len();
+                self.read(address, buffer);
+                Ok(())
+            }
+            _ => Err(SimError::UnknownCommand(cmd.opcode)),
+        }
+    }
+
+    fn set_command_width(&mut self, _width: BusWidth) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::Flash;
+    use crate::{Error, FlashInfo, FlashOperations, SecurityInfo};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    const ID: [u8; 3] = [0xEF, 0x40, 0x17];
+    const CAPACITY: usize = 8 * 1024 * 1024;
+
+    /// Simulator the test can still inspect once `Flash` owns the interface
+    #[derive(Clone)]
+    struct Shared {
+        sim: Rc<RefCell<SimulatedFlash>>,
+        /// Opcode to reject instead of passing it to the simulator
+        fail: Rc<Cell<Option<u8>>>,
+        /// Last multi-line read, to check its phases
+        last_read: Rc<Cell<Option<QuadCommand>>>,
+    }
+
+    impl Shared {
+        fn new(sim: SimulatedFlash) -> Self {
+            Self {
+                sim: Rc::new(RefCell::new(sim)),
+                fail: Rc::new(Cell::new(None)),
+                last_read: Rc::new(Cell::new(None)),
+            }
+        }
+
+        fn check(&self, opcode: u8) -> Result<(), SimError> {
+            if self.fail.get() == Some(opcode) {
+                return Err(SimError::UnknownCommand(opcode));
+            }
+            Ok(())
+        }
+    }
+
+    impl SerialInterface for Shared {
+        type Error = SimError;
+
+        fn write(&mut self, cmd: &[u8], data: Option<&[u8]>) -> Result<(), Self::Error> {
+            self.check(cmd[0])?;
+            self.sim.borrow_mut().write(cmd, data)
+        }
+
+        fn write_and_read(&mut self, cmd: &[u8], rev: &mut [u8]) -> Result<(), Self::Error> {
+            self.check(cmd[0])?;
+            self.sim.borrow_mut().write_and_read(cmd, rev)
+        }
+
+        fn delay(&mut self, ms: u32) {
+            self.sim.borrow_mut().delay(ms);
+        }
+
+        fn delay_us(&mut self, us: u32) {
+            self.sim.borrow_mut().delay_us(us);
+        }
+
+        fn supports_width(&self, width: BusWidth) -> bool {
+            self.sim.borrow().supports_width(width)
+        }
+
+        fn command_read(
+            &mut self,
+            cmd: &QuadCommand,
+            buffer: &mut [u8],
+        ) -> Result<(), Self::Error> {
+            self.check(cmd.opcode)?;
+            self.last_read.set(Some(*cmd));
+            self.sim.borrow_mut().command_read(cmd, buffer)
+        }
+    }
+
+    impl MemoryMappedInterface for Shared {
+        fn enter_memory_mapped(&mut self, cmd: &QuadCommand) -> Result<(), Self::Error> {
+            self.sim.borrow_mut().enter_memory_mapped(cmd)
+        }
+
+        fn exit_memory_mapped(&mut self) -> Result<(), Self::Error> {
+            self.sim.borrow_mut().exit_memory_mapped()
+        }
+    }
+
+    #[cfg(feature = "qspi")]
+    impl crate::serial_interface::QuadSerialInterface for Shared {
+        fn quad_write(
+            &mut self,
+            cmd: &QuadCommand,
+            data: Option<&[u8]>,
+        ) -> Result<(), Self::Error> {
+            self.check(cmd.opcode)?;
+            self.sim.borrow_mut().quad_write(cmd, data)
+        }
+
+        fn quad_read(&mut self, cmd: &QuadCommand, buffer: &mut [u8]) -> Result<(), Self::Error> {
+            self.check(cmd.opcode)?;
+            self.sim.borrow_mut().quad_read(cmd, buffer)
+        }
+
+        fn set_command_width(&mut self, width: BusWidth) {
+            self.sim.borrow_mut().set_command_width(width);
+        }
+    }
+
+    #[test]
+    fn write_read_erase() {
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096);
+        let mut flash = Flash::new(SimulatedFlash::new(ID, CAPACITY), info).unwrap();
+
+        // Crosses a page boundary
+        let data: Vec<u8> = (0..600).map(|i| (i * 7) as u8).collect();
+        flash.write_data(0x1080, &data).unwrap();
+        let mut buffer = vec![0_u8; 0x1000];
+        flash.read_data(0x1000, &mut buffer).unwrap();
+        assert!(buffer[..0x80].iter().all(|&b| b == 0xFF));
+        assert_eq!(&buffer[0x80..0x80 + data.len()], &data[..]);
+
+        flash.erase(0x1000, 0x1000).unwrap();
+        flash.read_data(0x1000, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn accesses_past_the_end_do_not_wrap() {
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096);
+        let mut flash = Flash::new(SimulatedFlash::new(ID, CAPACITY), info).unwrap();
+
+        let mut buffer = [0_u8; 16];
+        assert_eq!(
+            flash.read_data(u32::MAX - 4, &mut buffer),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            flash.write_data(u32::MAX - 4, &buffer),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            flash.read_data(CAPACITY as u32 - 8, &mut buffer),
+            Err(Error::OutOfBounds)
+        );
+        flash.read_data(CAPACITY as u32 - 16, &mut buffer).unwrap();
+    }
+
+    #[test]
+    fn erase_spans_block_sizes() {
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.memory_mut().fill(0x00);
+        let mut flash = Flash::from_sfdp(sim).unwrap();
+
+        flash.erase(0x1000, 0x2_1000).unwrap();
+        let mut buffer = vec![0_u8; 0x3_0000];
+        flash.read_data(0, &mut buffer).unwrap();
+        assert!(buffer[..0x1000].iter().all(|&b| b == 0x00));
+        assert!(buffer[0x1000..0x2_2000].iter().all(|&b| b == 0xFF));
+        assert!(buffer[0x2_2000..].iter().all(|&b| b == 0x00));
+    }
+
+    #[test]
+    fn erase_must_align_to_smallest_type() {
+        // Only 64K blocks can be erased: no 4K erase in DWORD 1, no erase types 1 and 2
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.sfdp_mut()[SFDP_BFPT_POINTER] |= 0b11;
+        sim.sfdp_mut()[SFDP_BFPT_POINTER + 28..SFDP_BFPT_POINTER + 32].fill(0);
+        let mut flash = Flash::from_sfdp(sim).unwrap();
+
+        assert_eq!(flash.erase(0x1000, 0x1_0000), Err(Error::NotAligned));
+        assert_eq!(flash.erase(0, 0x1_8000), Err(Error::NotAligned));
+        flash.erase(0x1_0000, 0x1_0000).unwrap();
+    }
+
+    #[test]
+    fn block_protect_needs_winbond_layout() {
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096);
+        let mut flash = Flash::new(SimulatedFlash::new(ID, CAPACITY), info).unwrap();
+        flash
+            .protect(CAPACITY as u32 - 0x1000..CAPACITY as u32)
+            .unwrap();
+        assert_eq!(
+            flash.protected_ranges(),
+            Ok(Some(CAPACITY as u32 - 0x1000..CAPACITY as u32))
+        );
+        assert_eq!(
+            flash.write_data(CAPACITY as u32 - 0x100, &[0; 4]),
+            Err(Error::WriteProtected)
+        );
+
+        // Macronix keeps BP3 and QE where Winbond has TB and SEC
+        let id = [0xC2, 0x20, 0x17];
+        let info = FlashInfo::new(0xC2, 0x20, 0x17, CAPACITY, 4096);
+        let mut flash = Flash::new(SimulatedFlash::new(id, CAPACITY), info).unwrap();
+        assert_eq!(flash.protect(0..0x1000), Err(Error::Unsupported));
+        assert_eq!(flash.protected_ranges(), Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn block_protect_table_by_capacity() {
+        // W25Q16 protects in 64K steps, which is not modelled
+        const SMALL: usize = 2 * 1024 * 1024;
+        let info = FlashInfo::new(0xEF, 0x40, 0x15, SMALL, 4096);
+        let sim = SimulatedFlash::new([0xEF, 0x40, 0x15], SMALL);
+        let mut flash = Flash::new(sim, info).unwrap();
+        assert_eq!(flash.protect(0..0x1000), Err(Error::Unsupported));
+
+        // W25Q128 uses SEC for the 4K sectors
+        const MID: usize = 16 * 1024 * 1024;
+        let info = FlashInfo::new(0xEF, 0x40, 0x18, MID, 4096);
+        let shared = Shared::new(SimulatedFlash::new([0xEF, 0x40, 0x18], MID));
+        let mut flash = Flash::new(shared.clone(), info).unwrap();
+        flash.protect(MID as u32 - 0x1000..MID as u32).unwrap();
+        assert_eq!(shared.sim.borrow().status[0] & 0x7C, 0x44);
+        assert_eq!(
+            flash.protected_ranges(),
+            Ok(Some(MID as u32 - 0x1000..MID as u32))
+        );
+
+        // W25Q256 has BP3 at bit 5 and TB at bit 6, and protects whole 64K blocks
+        const BIG: usize = 32 * 1024 * 1024;
+        let info = FlashInfo::new(0xEF, 0x40, 0x19, BIG, 4096)
+            .with_address_mode(crate::AddressMode::ExtendedAddress);
+        let shared = Shared::new(SimulatedFlash::new([0xEF, 0x40, 0x19], BIG));
+        let mut flash = Flash::new(shared.clone(), info).unwrap();
+        flash.protect(BIG as u32 - 0x1000..BIG as u32).unwrap();
+        assert_eq!(shared.sim.borrow().status[0] & 0x7C, 0x04);
+        assert_eq!(
+            flash.protected_ranges(),
+            Ok(Some(BIG as u32 - 0x1_0000..BIG as u32))
+        );
+        flash.unprotect(0..BIG as u32).unwrap();
+
+        flash.protect(0..0x2_0000).unwrap();
+        assert_eq!(shared.sim.borrow().status[0] & 0x7C, 0x48);
+        assert_eq!(flash.protected_ranges(), Ok(Some(0..0x2_0000)));
+        assert_eq!(
+            flash.write_data(0x1_0000, &[0; 4]),
+            Err(Error::WriteProtected)
+        );
+        flash.write_data(0x2_0000, &[0; 4]).unwrap();
+    }
+
+    #[test]
+    fn block_locks_above_16m() {
+        const BIG: usize = 32 * 1024 * 1024;
+        let id = [0xEF, 0x40, 0x19];
+        let info = FlashInfo::new(0xEF, 0x40, 0x19, BIG, 4096)
+            .with_address_mode(crate::AddressMode::ExtendedAddress);
+        let mut flash = Flash::new(SimulatedFlash::new(id, BIG), info).unwrap();
+        flash.set_block_locks(true, true).unwrap();
+        flash.unlock_all().unwrap();
+
+        flash.lock_block(0x180_0000).unwrap();
+        assert!(flash.is_block_locked(0x180_0000).unwrap());
+        assert!(!flash.is_block_locked(0x80_0000).unwrap());
+        assert_eq!(
+            flash.write_data(0x180_0000, &[0; 4]),
+            Err(Error::WriteProtected)
+        );
+        flash.write_data(0x80_0000, &[0; 4]).unwrap();
+    }
+
+    #[test]
+    fn security_registers_are_opt_in() {
+        let id = [0x9D, 0x60, 0x17];
+        let info = FlashInfo::new(0x9D, 0x60, 0x17, CAPACITY, 4096);
+        let mut flash = Flash::new(SimulatedFlash::new(id, CAPACITY), info).unwrap();
+        assert_eq!(flash.read_unique_id(), Err(Error::Unsupported));
+
+        let security = SecurityInfo {
+            count: 3,
+            size: 256,
+            unique_id_len: 8,
+        };
+        let info = FlashInfo::new(0x9D, 0x60, 0x17, CAPACITY, 4096).with_security(security);
+        let mut flash = Flash::new(SimulatedFlash::new(id, CAPACITY), info).unwrap();
+        assert!(flash.read_unique_id().is_ok());
+    }
+
+    #[test]
+    fn read_suspends_erase_elsewhere() {
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.set_busy_polls(50);
+        sim.memory_mut()[0x2_0000] = 0x12;
+        let shared = Shared::new(sim);
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.set_suspend_on_read(true).unwrap();
+
+        flash.start_erase(0x1000, 4096).unwrap();
+        let mut buffer = [0_u8; 1];
+        flash.read_data(0x2_0000, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x12]);
+        assert!(shared.sim.borrow().commands().contains(&0x75));
+        assert!(flash.is_busy().unwrap());
+        assert!(!flash.is_suspended().unwrap());
+
+        // Suspending again right after the resume waits out the interval first
+        let before = shared.sim.borrow().elapsed_us();
+        assert!(flash.suspend().unwrap());
+        assert!(shared.sim.borrow().elapsed_us() - before >= 64);
+        flash.resume().unwrap();
+    }
+
+    #[test]
+    fn read_of_erased_region_waits_for_erase() {
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.set_busy_polls(50);
+        let shared = Shared::new(sim);
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.set_suspend_on_read(true).unwrap();
+
+        flash.write_data(0x1000, &[0x34]).unwrap();
+        flash.start_erase(0x1000, 4096).unwrap();
+        shared.sim.borrow_mut().clear_commands();
+        let mut buffer = [0_u8; 1];
+        flash.read_data(0x1000, &mut buffer).unwrap();
+        assert_eq!(buffer, [0xFF]);
+        assert!(!shared.sim.borrow().commands().contains(&0x75));
+        assert!(!flash.is_busy().unwrap());
+    }
+
+    #[test]
+    fn failed_read_still_resumes() {
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.set_busy_polls(50);
+        let shared = Shared::new(sim);
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.set_suspend_on_read(true).unwrap();
+
+        flash.start_erase(0x1000, 4096).unwrap();
+        shared.fail.set(Some(define::ReadCmd::Data as u8));
+        assert_eq!(
+            flash.read_data(0x2_0000, &mut [0; 4]),
+            Err(Error::Interface(SimError::UnknownCommand(0x03)))
+        );
+        shared.fail.set(None);
+        assert!(!flash.is_suspended().unwrap());
+        assert!(flash.is_busy().unwrap());
+    }
+
+    #[test]
+    fn reset_reapplies_protection_handling() {
+        let top = CAPACITY as u32 - 0x1000..CAPACITY as u32;
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096);
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::new(shared.clone(), info).unwrap();
+        flash.protect(top.clone()).unwrap();
+        flash.reset().unwrap();
+        assert_eq!(shared.sim.borrow().status[0] & 0x7C, 0);
+        flash.write_data(top.start, &[0; 4]).unwrap();
+
+        // Kept protection is read back, here after something else cleared it
+        let options = crate::flash::InitOptions {
+            keep_protection: true,
+            ..Default::default()
+        };
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096);
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::with_options(shared.clone(), info, options).unwrap();
+        flash.protect(top.clone()).unwrap();
+        flash.reset().unwrap();
+        assert_eq!(shared.sim.borrow().status[0] & 0x7C, 0x44);
+        assert_eq!(
+            flash.write_data(top.start, &[0; 4]),
+            Err(Error::WriteProtected)
+        );
+        shared.sim.borrow_mut().status[0] &= !0x7C;
+        flash.reset().unwrap();
+        flash.write_data(top.start, &[0; 4]).unwrap();
+    }
+
+    #[test]
+    fn volatile_write_enable_by_manufacturer() {
+        for (id, used, unused) in [(ID, 0x50, 0x06), ([0xC2, 0x20, 0x17], 0x06, 0x50)] {
+            let info = FlashInfo::new(id[0], id[1], id[2], CAPACITY, 4096);
+            let shared = Shared::new(SimulatedFlash::new(id, CAPACITY));
+            let mut flash = Flash::new(shared.clone(), info).unwrap();
+            shared.sim.borrow_mut().clear_commands();
+
+            let mut sr = flash.read_status_registers().unwrap();
+            sr.set_quad_enable(true);
+            flash.write_status_registers(&sr, true).unwrap();
+            assert!(flash.read_status_registers().unwrap().quad_enable());
+            let commands = shared.sim.borrow().commands().to_vec();
+            assert!(commands.contains(&used));
+            assert!(!commands.contains(&unused));
+        }
+    }
+
+    #[test]
+    fn busy_wait_interval_and_timeout() {
+        let timeouts = crate::Timeouts {
+            page_program: 500,
+            write_status: 500,
+            ..Default::default()
+        };
+        let info = FlashInfo::new(0xEF, 0x40, 0x17, CAPACITY, 4096).with_timeouts(timeouts);
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::new(shared.clone(), info).unwrap();
+        shared.sim.borrow_mut().set_busy_polls(3);
+
+        // 500 / 100 is raised to the 10 us minimum interval
+        let before = shared.sim.borrow().elapsed_us();
+        flash.write_data(0, &[0]).unwrap();
+        assert_eq!(shared.sim.borrow().elapsed_us() - before, 30);
+
+        // The chip erase timeout is capped at 10 ms per poll
+        let before = shared.sim.borrow().elapsed_us();
+        flash.erase_chip().unwrap();
+        assert_eq!(shared.sim.borrow().elapsed_us() - before, 30_000);
+
+        shared.sim.borrow_mut().set_busy_polls(1000);
+        let before = shared.sim.borrow().elapsed_us();
+        assert_eq!(flash.write_data(0, &[0]), Err(Error::BusyTimeout));
+        // Gives up after the program timeout, then again on the write disable that follows
+        assert_eq!(shared.sim.borrow().elapsed_us() - before, 1000);
+    }
+
+    #[test]
+    fn auto_power_down_and_wake() {
+        let mut sim = SimulatedFlash::new(ID, CAPACITY);
+        sim.memory_mut()[0x10] = 0x34;
+        let shared = Shared::new(sim);
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.set_auto_power_down(Some(100)).unwrap();
+
+        flash.tick(50).unwrap();
+        assert!(!shared.sim.borrow().is_powered_down());
+        flash.tick(60).unwrap();
+        assert!(flash.is_powered_down());
+        assert!(shared.sim.borrow().is_powered_down());
+
+        // Every access wakes the chip first, a powered down chip reads 0xFF
+        let mut buffer = [0_u8; 1];
+        flash.read_data(0x10, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x34]);
+        assert!(!shared.sim.borrow().is_powered_down());
+
+        // A running erase postpones the power-down, the access restarted the idle period
+        flash.tick(99).unwrap();
+        assert!(!flash.is_powered_down());
+        shared.sim.borrow_mut().set_busy_polls(2);
+        flash.start_erase(0x1000, 4096).unwrap();
+        flash.tick(200).unwrap();
+        assert!(!shared.sim.borrow().is_powered_down());
+        flash.tick(1).unwrap();
+        flash.tick(1).unwrap();
+        assert!(shared.sim.borrow().is_powered_down());
+
+        flash.write_data(0x1000, &[0x56]).unwrap();
+        assert!(!shared.sim.borrow().is_powered_down());
+        assert_eq!(shared.sim.borrow().memory()[0x1000], 0x56);
+    }
+
+    #[test]
+    fn identify_reads_every_id() {
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.power_down().unwrap();
+
+        let id = flash.identify().unwrap();
+        assert!(!shared.sim.borrow().is_powered_down());
+        assert_eq!(id.jedec_id(), ID);
+        assert_eq!(id.continuation, 0);
+        assert_eq!(id.device_id, 0x16);
+        assert_eq!(id.manufacturer_device_id, [0xEF, 0x16]);
+        assert_eq!(id.manufacturer_name(), Some("Winbond"));
+        assert_eq!(crate::id::manufacturer_name(1, 0xEF), None);
+    }
+
+    #[test]
+    fn probe_uses_part_table_before_sfdp() {
+        const BIG: usize = 32 * 1024 * 1024;
+        let shared = Shared::new(SimulatedFlash::new([0xEF, 0x40, 0x19], BIG));
+        let mut flash = Flash::probe(shared.clone()).unwrap();
+        assert!(!shared.sim.borrow().commands().contains(&0x5A));
+        assert_eq!(flash.flash_info().capacity(), BIG);
+        assert_eq!(
+            flash.flash_info().address_mode(),
+            crate::AddressMode::FourByteOpcodes
+        );
+        flash.write_data(0x180_0000, &[0x12]).unwrap();
+        assert_eq!(shared.sim.borrow().memory()[0x180_0000], 0x12);
+
+        // Quirks of the table entry replace the Winbond defaults
+        const SMALL: usize = 4 * 1024 * 1024;
+        let sim = SimulatedFlash::new([0x20, 0xBA, 0x16], SMALL);
+        let mut flash = Flash::probe(sim).unwrap();
+        assert_eq!(flash.power_down(), Err(Error::Unsupported));
+
+        let shared = Shared::new(SimulatedFlash::new([0xAA, 0x40, 0x17], CAPACITY));
+        let flash = Flash::probe(shared.clone()).unwrap();
+        assert!(shared.sim.borrow().commands().contains(&0x5A));
+        assert_eq!(flash.flash_info().capacity(), CAPACITY);
+        assert_eq!(
+            crate::parts::find([0x20, 0xBA, 0x16]).unwrap().name,
+            "N25Q032"
+        );
+    }
+
+    #[test]
+    fn address_modes_cross_16m() {
+        use crate::AddressMode;
+
+        const BIG: usize = 32 * 1024 * 1024;
+        let id = [0xEF, 0x40, 0x19];
+        let data: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        for mode in [
+            AddressMode::FourByteOpcodes,
+            AddressMode::ExtendedAddress,
+            AddressMode::BankRegister,
+            AddressMode::Enter4Byte {
+                write_enable: false,
+            },
+        ] {
+            let info = FlashInfo::new(0xEF, 0x40, 0x19, BIG, 4096).with_address_mode(mode);
+            let shared = Shared::new(SimulatedFlash::new(id, BIG));
+            let mut flash = Flash::new(shared.clone(), info).unwrap();
+            assert_eq!(
+                shared.sim.borrow().is_address_4_byte(),
+                matches!(mode, AddressMode::Enter4Byte { .. }),
+                "{:?}",
+                mode
+            );
+
+            flash.write_data(0xFF_FF00, &data).unwrap();
+            assert_eq!(
+                shared.sim.borrow().memory()[0xFF_FF00..0x100_0100],
+                data[..]
+            );
+            // Segment modes go back to the first 16M after every operation
+            assert_eq!(shared.sim.borrow().extended_address(), 0, "{:?}", mode);
+            assert_eq!(shared.sim.borrow().memory()[..0x100], [0xFF; 0x100]);
+
+            let mut buffer = std::vec![0_u8; 512];
+            flash.read_data(0xFF_FF00, &mut buffer).unwrap();
+            assert_eq!(buffer, data, "{:?}", mode);
+            flash.erase(0x100_0000, 4096).unwrap();
+            assert_eq!(shared.sim.borrow().memory()[0x100_0000], 0xFF);
+            assert_eq!(shared.sim.borrow().memory()[0xFF_FFFE], 0xFE);
+        }
+    }
+
+    #[test]
+    fn read_mode_opcodes_and_dummy_cycles() {
+        use crate::flash::ReadMode;
+
+        // 3-byte opcodes at 8M, 4-byte opcodes at 32M
+        for (id, capacity, opcodes) in [
+            (ID, CAPACITY, [0x0B, 0x3B, 0xBB]),
+            ([0xEF, 0x40, 0x19], 32 * 1024 * 1024, [0x0C, 0x3C, 0xBC]),
+        ] {
+            let address = capacity as u32 - 0x1000;
+            let shared = Shared::new(SimulatedFlash::new(id, capacity));
+            let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+            flash.write_data(address, &[1, 2, 3]).unwrap();
+
+            // 8 dummy clocks on one line, 1-2-2 spends its 4 clocks on mode bits that stay out of XIP
+            let expected = [
+                (ReadMode::Fast, BusWidth::Single, BusWidth::Single, None, 8),
+                (
+                    ReadMode::DualOutput,
+                    BusWidth::Single,
+                    BusWidth::Dual,
+                    None,
+                    8,
+                ),
+                (
+                    ReadMode::DualIo,
+                    BusWidth::Dual,
+                    BusWidth::Dual,
+                    Some(0xFF),
+                    0,
+                ),
+            ];
+            for ((mode, address_width, data_width, mode_bits, dummy), opcode) in
+                expected.into_iter().zip(opcodes)
+            {
+                flash.set_read_mode(mode).unwrap();
+                let mut buffer = [0_u8; 3];
+                flash.read_data(address, &mut buffer).unwrap();
+                assert_eq!(buffer, [1, 2, 3], "{:?}", mode);
+
+                let cmd = shared.last_read.take().unwrap();
+                assert_eq!(cmd.opcode, opcode, "{:?}", mode);
+                assert_eq!(cmd.address_width, address_width, "{:?}", mode);
+                assert_eq!(cmd.data_width, data_width, "{:?}", mode);
+                assert_eq!(cmd.mode_bits, mode_bits, "{:?}", mode);
+                assert_eq!(cmd.dummy_cycles, dummy, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn xip_arms_with_a5_and_exits_before_access() {
+        use crate::flash::ReadMode;
+
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        assert_eq!(flash.enter_xip(), Err(Error::Unsupported));
+
+        flash.set_read_mode(ReadMode::DualIo).unwrap();
+        flash.enter_xip().unwrap();
+        let cmd = shared.last_read.take().unwrap();
+        assert_eq!((cmd.opcode, cmd.mode_bits), (0xBB, Some(0xA5)));
+        assert!(shared.sim.borrow().is_continuous_read());
+
+        // The simulator rejects every opcode in continuous read mode, so these only
+        // pass if XIP is left first
+        flash.write_data(0x2000, &[0x12]).unwrap();
+        assert!(!flash.is_xip());
+        assert!(!shared.sim.borrow().is_continuous_read());
+
+        flash.enter_xip().unwrap();
+        flash.erase(0x2000, 4096).unwrap();
+        flash.enter_xip().unwrap();
+        let mut buffer = [0_u8; 1];
+        flash.read_data(0x2000, &mut buffer).unwrap();
+        assert_eq!(buffer, [0xFF]);
+
+        // Auto power-down leaves a chip in XIP alone, an explicit one exits first
+        flash.enter_xip().unwrap();
+        flash.set_auto_power_down(Some(10)).unwrap();
+        flash.tick(20).unwrap();
+        assert!(shared.sim.borrow().is_continuous_read());
+        flash.power_down().unwrap();
+        assert!(!flash.is_xip());
+        assert!(shared.sim.borrow().is_powered_down());
+    }
+
+    #[cfg(feature = "qspi")]
+    #[test]
+    fn xip_uses_quad_io_read() {
+        use crate::flash::QuadMode;
+
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.set_quad_mode(QuadMode::QuadIo).unwrap();
+        let cmd = flash.xip_command().unwrap();
+        assert_eq!((cmd.opcode, cmd.mode_bits), (0xEB, Some(0xA5)));
+        flash.enter_xip().unwrap();
+        assert!(shared.sim.borrow().is_continuous_read());
+        flash.reset().unwrap();
+        assert!(!flash.is_xip());
+        assert!(!shared.sim.borrow().is_continuous_read());
+    }
+
+    #[test]
+    fn memory_mapped_falls_back_to_indirect() {
+        use crate::flash::ReadMode;
+
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        flash.write_data(0x3000, b"mapped").unwrap();
+        flash.set_read_mode(ReadMode::Fast).unwrap();
+        let cmd = flash.memory_mapped_command(false).unwrap();
+        assert_eq!(
+            (cmd.opcode, cmd.dummy_cycles, cmd.address, cmd.address_len),
+            (0x0B, 8, None, 3)
+        );
+
+        flash.enter_memory_mapped(false).unwrap();
+        assert!(flash.is_memory_mapped());
+        let mut buffer = [0_u8; 6];
+        shared
+            .sim
+            .borrow_mut()
+            .mapped_read(0x3000, &mut buffer)
+            .unwrap();
+        assert_eq!(&buffer, b"mapped");
+
+        // The simulator rejects indirect commands while mapped
+        flash.erase(0x3000, 4096).unwrap();
+        assert!(!flash.is_memory_mapped());
+        assert!(shared.sim.borrow().memory_mapped_command().is_none());
+
+        // Mapped XIP: the controller skips the opcode once the mode bits armed it
+        flash.set_read_mode(ReadMode::DualIo).unwrap();
+        flash.enter_memory_mapped(true).unwrap();
+        let cmd = shared.sim.borrow().memory_mapped_command().unwrap();
+        assert_eq!(
+            (cmd.opcode, cmd.mode_bits, cmd.address_width),
+            (0xBB, Some(0xA5), BusWidth::Dual)
+        );
+        shared
+            .sim
+            .borrow_mut()
+            .mapped_read(0x3000, &mut buffer)
+            .unwrap();
+        shared.sim.borrow_mut().clear_commands();
+        shared
+            .sim
+            .borrow_mut()
+            .mapped_read(0x3000, &mut buffer)
+            .unwrap();
+        assert!(shared.sim.borrow().commands().is_empty());
+        assert_eq!(buffer, [0xFF; 6]);
+
+        flash.write_data(0x3000, b"again!").unwrap();
+        assert!(!flash.is_xip() && !flash.is_memory_mapped());
+        assert!(!shared.sim.borrow().is_continuous_read());
+        flash.read_data(0x3000, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"again!");
+    }
+
+    #[test]
+    fn update_erases_only_when_bits_are_set() {
+        const SECTOR_ERASE: u8 = define::EraseCmd::Sector4k as u8;
+        const PAGE_PROGRAM: u8 = define::WriteCmd::PageProgram as u8;
+
+        let shared = Shared::new(SimulatedFlash::new(ID, CAPACITY));
+        let mut flash = Flash::from_sfdp(shared.clone()).unwrap();
+        let mut scratch = std::vec![0_u8; 4096];
+        let before: Vec<u8> = (0..0x3000).map(|i| (i * 13) as u8 | 0x0F).collect();
+        flash.write_data(0x1000, &before).unwrap();
+
+        // Only clears bits: programmed in place
+        shared.sim.borrow_mut().clear_commands();
+        flash.update(0x1800, &[0x00, 0x01], &mut scratch).unwrap();
+        assert!(!shared.sim.borrow().commands().contains(&SECTOR_ERASE));
+        assert!(shared.sim.borrow().commands().contains(&PAGE_PROGRAM));
+
+        // Already there: nothing written
+        shared.sim.borrow_mut().clear_commands();
+        flash.update(0x1800, &[0x00, 0x01], &mut scratch).unwrap();
+        assert!(!shared.sim.borrow().commands().contains(&PAGE_PROGRAM));
+
+        // Sets bits in two sectors: both erased, their other bytes kept
+        shared.sim.borrow_mut().clear_commands();
+        flash.update(0x1F00, &[0xF0; 0x200], &mut scratch).unwrap();
+        let erases = shared
+            .sim
+            .borrow()
+            .commands()
+            .iter()
+            .filter(|&&c| c == SECTOR_ERASE)
+            .count();
+        assert_eq!(erases, 2);
+
+        let mut expected = before;
+        expected[0x800] = 0x00;
+        expected[0x801] = 0x01;
+        expected[0xF00..0x1100].fill(0xF0);
+        assert_eq!(shared.sim.borrow().memory()[0x1000..0x4000], expected[..]);
+
+        assert_eq!(
+            flash.update(0, &[1], &mut [0; 16]),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(
+            flash.update(CAPACITY as u32 - 1, &[1, 2], &mut scratch),
+            Err(Error::OutOfBounds)
+        );
+    }
+}
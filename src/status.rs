@@ -0,0 +1,172 @@
+use crate::define::STATUS;
+
+const BP_MASK: u32 = STATUS::BP0 as u32 | STATUS::BP1 as u32 | STATUS::BP2 as u32;
+const LB_MASK: u32 = STATUS::LB1 as u32 | STATUS::LB2 as u32 | STATUS::LB3 as u32;
+const DRV_MASK: u32 = STATUS::DRV0 as u32 | STATUS::DRV1 as u32;
+/// Winbond parts of 32 MiB and up put BP3 at TB and TB at SEC
+const WIDE_BP_MASK: u32 = BP_MASK | STATUS::TB as u32;
+const WIDE_TB: STATUS = STATUS::SEC;
+
+/// Status registers 1 to 3 with the common Winbond bit layout
+///
+/// Read with 0x05/0x35/0x15 and written with 0x01/0x31/0x11. Bits without an
+/// accessor are kept as read, so a read-modify-write leaves them untouched.
+/// SR2 and SR3 read as 0 on parts that do not have them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusRegisters {
+    /// SR1 in bits 7:0, SR2 in bits 15:8, SR3 in bits 23:16
+    bits: u32,
+}
+
+impl StatusRegisters {
+    pub fn from_bytes(sr: [u8; 3]) -> Self {
+        StatusRegisters {
+            bits: u32::from_le_bytes([sr[0], sr[1], sr[2], 0]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let bytes = self.bits.to_le_bytes();
+        [bytes[0], bytes[1], bytes[2]]
+    }
+
+    fn get(&self, bit: STATUS) -> bool {
+        self.bits & bit as u32 != 0
+    }
+
+    fn set(&mut self, bit: STATUS, value: bool) {
+        if value {
+            self.bits |= bit as u32;
+        } else {
+            self.bits &= !(bit as u32);
+        }
+    }
+
+    fn get_field(&self, mask: u32) -> u8 {
+        ((self.bits & mask) >> mask.trailing_zeros()) as u8
+    }
+
+    fn set_field(&mut self, mask: u32, value: u8) {
+        self.bits = (self.bits & !mask) | (((value as u32) << mask.trailing_zeros()) & mask);
+    }
+
+    pub fn busy(&self) -> bool {
+        self.get(STATUS::BUSY)
+    }
+
+    pub fn write_enable_latch(&self) -> bool {
+        self.get(STATUS::WEL)
+    }
+
+    /// BP2:0
+    pub fn block_protect(&self) -> u8 {
+        self.get_field(BP_MASK)
+    }
+
+    pub fn set_block_protect(&mut self, value: u8) {
+        self.set_field(BP_MASK, value);
+    }
+
+    /// TB, protect from the bottom instead of the top
+    pub fn top_bottom(&self) -> bool {
+        self.get(STATUS::TB)
+    }
+
+    pub fn set_top_bottom(&mut self, value: bool) {
+        self.set(STATUS::TB, value);
+    }
+
+    /// BP3:0 on parts of 32 MiB and up, which have no SEC bit
+    pub fn wide_block_protect(&self) -> u8 {
+        self.get_field(WIDE_BP_MASK)
+    }
+
+    pub fn set_wide_block_protect(&mut self, value: u8) {
+        self.set_field(WIDE_BP_MASK, value);
+    }
+
+    /// TB on parts of 32 MiB and up, in SR1 bit 6
+    pub fn wide_top_bottom(&self) -> bool {
+        self.get(WIDE_TB)
+    }
+
+    pub fn set_wide_top_bottom(&mut self, value: bool) {
+        self.set(WIDE_TB, value);
+    }
+
+    /// SEC, protect 4 KiB sectors instead of 64 KiB blocks
+    pub fn sector_protect(&self) -> bool {
+        self.get(STATUS::SEC)
+    }
+
+    pub fn set_sector_protect(&mut self, value: bool) {
+        self.set(STATUS::SEC, value);
+    }
+
+    /// CMP, invert the protected area
+    pub fn complement_protect(&self) -> bool {
+        self.get(STATUS::CMP)
+    }
+
+    pub fn set_complement_protect(&mut self, value: bool) {
+        self.set(STATUS::CMP, value);
+    }
+
+    pub fn status_register_protect(&self) -> (bool, bool) {
+        (self.get(STATUS::SRP0), self.get(STATUS::SRP1))
+    }
+
+    pub fn set_status_register_protect(&mut self, srp0: bool, srp1: bool) {
+        self.set(STATUS::SRP0, srp0);
+        self.set(STATUS::SRP1, srp1);
+    }
+
+    pub fn quad_enable(&self) -> bool {
+        self.get(STATUS::QE)
+    }
+
+    pub fn set_quad_enable(&mut self, value: bool) {
+        self.set(STATUS::QE, value);
+    }
+
+    /// LB3:1, one-time programmable security register locks
+    pub fn lock_bits(&self) -> u8 {
+        self.get_field(LB_MASK)
+    }
+
+    /// Setting a lock bit in the non-volatile register cannot be undone
+    pub fn set_lock_bits(&mut self, value: u8) {
+        self.set_field(LB_MASK, value);
+    }
+
+    pub fn suspended(&self) -> bool {
+        self.get(STATUS::SUS)
+    }
+
+    /// WPS, use the individual block locks instead of the BP bits
+    pub fn write_protect_selection(&self) -> bool {
+        self.get(STATUS::WPS)
+    }
+
+    pub fn set_write_protect_selection(&mut self, value: bool) {
+        self.set(STATUS::WPS, value);
+    }
+
+    /// DRV1:0, 0 is the strongest output driver
+    pub fn drive_strength(&self) -> u8 {
+        self.get_field(DRV_MASK)
+    }
+
+    pub fn set_drive_strength(&mut self, value: u8) {
+        self.set_field(DRV_MASK, value);
+    }
+
+    /// HOLD/RST, pin is /RESET instead of /HOLD
+    pub fn hold_reset(&self) -> bool {
+        self.get(STATUS::HOLD)
+    }
+
+    pub fn set_hold_reset(&mut self, value: bool) {
+        self.set(STATUS::HOLD, value);
+    }
+}
@@ -0,0 +1,254 @@
+//! `embedded-storage` traits for `Flash` and `AsyncFlash`, through `NorStorage`
+
+use core::fmt::Debug;
+
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use log::error;
+
+use crate::flash::Flash;
+use crate::serial_interface::SerialInterface;
+use crate::{Error, FlashInfo, FlashOperations};
+
+/// `Flash` or `AsyncFlash` with the erase size `NorFlash` reports fixed to `ERASE`
+///
+/// `NorFlash::ERASE_SIZE` is a constant, so the erase size is part of the type. `new`
+/// checks it once against the sector size and the erase types of the part, every
+/// `ERASE` aligned range can then be erased.
+pub struct NorStorage<F, const ERASE: usize = 4096> {
+    flash: F,
+}
+
+impl<F, const ERASE: usize> NorStorage<F, ERASE> {
+    pub fn flash(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+}
+
+impl<I, const ERASE: usize> NorStorage<Flash<I>, ERASE>
+where
+    I: SerialInterface,
+{
+    pub fn new(flash: Flash<I>) -> Result<Self, Error<I::Error>> {
+        check_erase_size(flash.flash_info(), ERASE)?;
+        Ok(NorStorage { flash })
+    }
+}
+
+impl<E: Debug> NorFlashError for Error<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Error::NotAligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// Check an access of `len` bytes at `offset` against alignment and capacity
+fn check_access<E>(capacity: usize, offset: u32, len: usize, align: usize) -> Result<(), Error<E>> {
+    let offset = offset as usize;
+    if !offset.is_multiple_of(align) || !len.is_multiple_of(align) {
+        return Err(Error::NotAligned);
+    }
+    if offset > capacity || len > capacity - offset {
+        return Err(Error::OutOfBounds);
+    }
+    Ok(())
+}
+
+/// An erase size must cover whole sectors and whole erases of the smallest type
+fn check_erase_size<E>(info: &FlashInfo, erase: usize) -> Result<(), Error<E>> {
+    if erase == 0 || !info.capacity().is_multiple_of(erase) {
+        error!("Erase size {} does not divide the capacity", erase);
+        return Err(Error::NotAligned);
+    }
+    info.check_erase_alignment(0, erase)
+}
+
+/// Check an erase from `from` to `to` against `erase`, the size checked by `NorStorage::new`
+fn check_erase<E>(capacity: usize, from: u32, to: u32, erase: usize) -> Result<(), Error<E>> {
+    if from > to {
+        return Err(Error::OutOfBounds);
+    }
+    check_access(capacity, from, (to - from) as usize, erase)
+}
+
+impl<I, const ERASE: usize> ErrorType for NorStorage<Flash<I>, ERASE>
+where
+    I: SerialInterface,
+    I::Error: Debug,
+{
+    type Error = Error<I::Error>;
+}
+
+impl<I, const ERASE: usize> ReadNorFlash for NorStorage<Flash<I>, ERASE>
+where
+    I: SerialInterface,
+    I::Error: Debug,
+{
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_access(self.capacity(), offset, bytes.len(), Self::READ_SIZE)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.flash.read_data(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.flash.flash_info().capacity()
+    }
+}
+
+impl<I, const ERASE: usize> NorFlash for NorStorage<Flash<I>, ERASE>
+where
+    I: SerialInterface,
+    I::Error: Debug,
+{
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = ERASE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self.capacity(), from, to, ERASE)?;
+        if from == to {
+            return Ok(());
+        }
+        FlashOperations::erase(&mut self.flash, from, (to - from) as usize)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_access(self.capacity(), offset, bytes.len(), Self::WRITE_SIZE)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.flash.write_data(offset, bytes)
+    }
+}
+
+impl<I, const ERASE: usize> MultiwriteNorFlash for NorStorage<Flash<I>, ERASE>
+where
+    I: SerialInterface,
+    I::Error: Debug,
+{
+}
+
+#[cfg(feature = "async")]
+mod asynch {
+    use core::fmt::Debug;
+
+    use embedded_storage::nor_flash::ErrorType;
+    use embedded_storage_async::nor_flash::{MultiwriteNorFlash, NorFlash, ReadNorFlash};
+
+    use super::{NorStorage, check_access, check_erase, check_erase_size};
+    use crate::async_flash::AsyncFlash;
+    use crate::serial_interface::AsyncSerialInterface;
+    use crate::{AsyncFlashOperations, Error};
+
+    impl<I, const ERASE: usize> NorStorage<AsyncFlash<I>, ERASE>
+    where
+        I: AsyncSerialInterface,
+    {
+        pub fn new(flash: AsyncFlash<I>) -> Result<Self, Error<I::Error>> {
+            check_erase_size(flash.flash_info(), ERASE)?;
+            Ok(NorStorage { flash })
+        }
+    }
+
+    impl<I, const ERASE: usize> ErrorType for NorStorage<AsyncFlash<I>, ERASE>
+    where
+        I: AsyncSerialInterface,
+        I::Error: Debug,
+    {
+        type Error = Error<I::Error>;
+    }
+
+    impl<I, const ERASE: usize> ReadNorFlash for NorStorage<AsyncFlash<I>, ERASE>
+    where
+        I: AsyncSerialInterface,
+        I::Error: Debug,
+    {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_access(self.capacity(), offset, bytes.len(), Self::READ_SIZE)?;
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            self.flash.read_data(offset, bytes).await
+        }
+
+        fn capacity(&self) -> usize {
+            self.flash.flash_info().capacity()
+        }
+    }
+
+    impl<I, const ERASE: usize> NorFlash for NorStorage<AsyncFlash<I>, ERASE>
+    where
+        I: AsyncSerialInterface,
+        I::Error: Debug,
+    {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = ERASE;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self.capacity(), from, to, ERASE)?;
+            if from == to {
+                return Ok(());
+            }
+            AsyncFlashOperations::erase(&mut self.flash, from, (to - from) as usize).await
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_access(self.capacity(), offset, bytes.len(), Self::WRITE_SIZE)?;
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            self.flash.write_data(offset, bytes).await
+        }
+    }
+
+    impl<I, const ERASE: usize> MultiwriteNorFlash for NorStorage<AsyncFlash<I>, ERASE>
+    where
+        I: AsyncSerialInterface,
+        I::Error: Debug,
+    {
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use embedded_storage::nor_flash::NorFlash;
+
+    use super::NorStorage;
+    use crate::Error;
+    use crate::flash::Flash;
+    use crate::sim::SimulatedFlash;
+
+    #[test]
+    fn erase_size_checked_once() {
+        const CAPACITY: usize = 8 * 1024 * 1024;
+        let flash = || {
+            let mut sim = SimulatedFlash::new([0xEF, 0x40, 0x17], CAPACITY);
+            // BFPT at 0x30: no 4K erase in DWORD 1, no erase types 1 and 2
+            sim.sfdp_mut()[0x30] |= 0b11;
+            sim.sfdp_mut()[0x30 + 28..0x30 + 32].fill(0);
+            Flash::from_sfdp(sim).unwrap()
+        };
+
+        assert!(matches!(
+            NorStorage::<Flash<_>, 4096>::new(flash()),
+            Err(Error::NotAligned)
+        ));
+        let mut storage = NorStorage::<Flash<_>, 0x1_0000>::new(flash()).unwrap();
+        assert_eq!(storage.erase(0x1000, 0x1_1000), Err(Error::NotAligned));
+        storage.erase(0x1_0000, 0x2_0000).unwrap();
+    }
+}